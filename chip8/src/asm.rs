@@ -0,0 +1,319 @@
+//! An assembler accepting the Octo and classic mnemonics produced by the
+//! disassembler, so disassembling and reassembling a ROM reproduces it.
+//!
+//! Besides instructions, a source file may contain
+//! - labels, written `: name` or `name:`
+//! - constants and register names, written `:alias name value` (or `:const`)
+//! - data, written `db 1, 2, 3`, `dw 0x1234` or as bare bytes `0x01 0x02`
+//! - comments starting with `#` or `;`
+use crate::EmuError;
+use std::collections::HashMap;
+
+/// The address programs are loaded at.
+const START: usize = 0x200;
+
+struct Token<'a> {
+    text: &'a str,
+    column: usize,
+}
+
+/// An error at a column of the line being assembled.
+type LineError = (usize, String);
+
+fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let code = line.split(['#', ';']).next().unwrap_or("");
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in code.char_indices().chain([(code.len(), ' ')]) {
+        let separator = c.is_whitespace() || c == ',';
+        match start {
+            Some(s) if separator => {
+                tokens.push(Token { text: &code[s..i], column: code[..s].chars().count() + 1 });
+                start = None;
+            },
+            None if !separator => start = Some(i),
+            _ => (),
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// Returns the number of bytes a statement assembles to, which doesn't depend on any label.
+fn size(words: &[String]) -> usize {
+    match words.first().map(String::as_str) {
+        Some("db") => words.len() - 1,
+        Some("dw") => 2 * (words.len() - 1),
+        Some(word) if parse_number(word).is_some() => words.len(),
+        _ if words.iter().any(|word| word == "long") => 4,
+        _ => 2,
+    }
+}
+
+struct Assembler {
+    labels: HashMap<String, usize>,
+    aliases: HashMap<String, String>,
+}
+
+impl Assembler {
+    /// Follows aliases until reaching a name which isn't one.
+    fn resolve<'a>(&'a self, mut text: &'a str) -> &'a str {
+        for _ in 0..self.aliases.len() {
+            match self.aliases.get(text) {
+                Some(target) => text = target,
+                None => break,
+            }
+        }
+        text
+    }
+    fn register(&self, token: &Token) -> Result<u16, LineError> {
+        let text = self.resolve(token.text).to_ascii_lowercase();
+        text.strip_prefix('v')
+            .filter(|index| index.len() == 1)
+            .and_then(|index| u16::from_str_radix(index, 16).ok())
+            .ok_or_else(|| (token.column, format!("expected a register, found '{}'", token.text)))
+    }
+    fn is_register(&self, token: &Token) -> bool {
+        self.register(token).is_ok()
+    }
+    fn value(&self, token: &Token, max: u16) -> Result<u16, LineError> {
+        let text = self.resolve(token.text);
+        let value = parse_number(text)
+            .map(|value| value as usize)
+            .or_else(|| self.labels.get(text).copied())
+            .ok_or_else(|| (token.column, format!("unknown value '{}'", token.text)))?;
+        u16::try_from(value)
+            .ok()
+            .filter(|&value| value <= max)
+            .ok_or_else(|| (token.column, format!("{} does not fit in {} bits", token.text, 16 - max.leading_zeros())))
+    }
+    /// Assembles `x op y` where `y` is a register or, for some operators, a byte.
+    fn arithmetic(&self, op: &str, x: &Token, y: &Token) -> Result<u16, LineError> {
+        let (nibble, immediate) = alu(op).unwrap_or_default();
+        let x = self.register(x)? << 8;
+        match immediate {
+            _ if self.is_register(y) => Ok(0x8000 | x | self.register(y)? << 4 | nibble),
+            Some(base) => Ok(base | x | self.value(y, 0xFF)?),
+            None => Err((y.column, format!("expected a register, found '{}'", y.text))),
+        }
+    }
+    /// Assembles one statement, which has at least one token.
+    fn encode(&self, tokens: &[Token]) -> Result<Vec<u8>, LineError> {
+        let lower: Vec<String> = tokens.iter().map(|token| token.text.to_ascii_lowercase()).collect();
+        let words: Vec<&str> = lower.iter().map(String::as_str).collect();
+        let last = tokens.len() - 1;
+        let x = || self.register(&tokens[1]).map(|x| x << 8);
+        let xy = |y: usize| Ok::<u16, LineError>(self.register(&tokens[1])? << 8 | self.register(&tokens[y])? << 4);
+        let v = |i: usize, max: u16| self.value(&tokens[i], max);
+
+        if words[0] == "db" || parse_number(words[0]).is_some() {
+            let first = usize::from(words[0] == "db");
+            return tokens[first..].iter().map(|token| Ok(self.value(token, 0xFF)? as u8)).collect();
+        }
+        if words[0] == "dw" {
+            let mut bytes = Vec::new();
+            for token in &tokens[1..] {
+                bytes.extend_from_slice(&self.value(token, 0xFFFF)?.to_be_bytes());
+            }
+            return Ok(bytes);
+        }
+        if let ["ld", "i", "long", _] | ["i", ":=", "long", _] = words.as_slice() {
+            let mut bytes = vec![0xF0, 0x00];
+            bytes.extend_from_slice(&v(3, 0xFFFF)?.to_be_bytes());
+            return Ok(bytes);
+        }
+
+        let opcode = match words.as_slice() {
+            ["cls" | "clear"] => 0x00E0,
+            ["ret" | "return"] => 0x00EE,
+            ["scr" | "scroll-right"] => 0x00FB,
+            ["scl" | "scroll-left"] => 0x00FC,
+            ["exit"] => 0x00FD,
+            ["low" | "lores"] => 0x00FE,
+            ["high" | "hires"] => 0x00FF,
+            ["audio"] => 0xF002,
+            ["scd" | "scroll-down", _] => 0x00C0 | v(1, 0xF)?,
+            ["scu" | "scroll-up", _] => 0x00D0 | v(1, 0xF)?,
+            ["plane", _] => 0xF001 | v(1, 0xF)? << 8,
+            ["jp", "v0", _] | ["jump0", _] => 0xB000 | v(last, 0xFFF)?,
+            ["jp" | "jump", _] => 0x1000 | v(1, 0xFFF)?,
+            ["call" | ":call", _] => 0x2000 | v(1, 0xFFF)?,
+            ["se", _, _] if self.is_register(&tokens[2]) => 0x5000 | xy(2)?,
+            ["se", _, _] => 0x3000 | x()? | v(2, 0xFF)?,
+            ["sne", _, _] if self.is_register(&tokens[2]) => 0x9000 | xy(2)?,
+            ["sne", _, _] => 0x4000 | x()? | v(2, 0xFF)?,
+            ["if", _, "!=", _, "then"] if self.is_register(&tokens[3]) => 0x5000 | xy(3)?,
+            ["if", _, "!=", _, "then"] => 0x3000 | x()? | v(3, 0xFF)?,
+            ["if", _, "==", _, "then"] if self.is_register(&tokens[3]) => 0x9000 | xy(3)?,
+            ["if", _, "==", _, "then"] => 0x4000 | x()? | v(3, 0xFF)?,
+            ["if", _, "-key", "then"] | ["skp", _] => 0xE09E | x()?,
+            ["if", _, "key", "then"] | ["sknp", _] => 0xE0A1 | x()?,
+            ["save", _] | ["ld", "[i]", _] => 0xF055 | self.register(&tokens[last])? << 8,
+            ["load", _] => 0xF065 | x()?,
+            ["ld", _, "[i]"] => 0xF065 | x()?,
+            ["save", _, _] | ["save", _, "-", _] => 0x5002 | xy(last)?,
+            ["load", _, _] | ["load", _, "-", _] => 0x5003 | xy(last)?,
+            ["saveflags", _] => 0xF075 | x()?,
+            ["ld", "r", _] => 0xF075 | self.register(&tokens[2])? << 8,
+            ["loadflags", _] | ["ld", _, "r"] => 0xF085 | x()?,
+            ["bcd", _] => 0xF033 | x()?,
+            ["ld", "b", _] => 0xF033 | self.register(&tokens[2])? << 8,
+            ["i", ":=", "hex", _] | ["ld", "f", _] => 0xF029 | self.register(&tokens[last])? << 8,
+            ["i", ":=", "bighex", _] | ["ld", "hf", _] => 0xF030 | self.register(&tokens[last])? << 8,
+            ["i", ":=", _] | ["ld", "i", _] => 0xA000 | v(2, 0xFFF)?,
+            ["i", "+=", _] | ["add", "i", _] => 0xF01E | self.register(&tokens[2])? << 8,
+            ["delay", ":=", _] | ["ld", "dt", _] => 0xF015 | self.register(&tokens[2])? << 8,
+            ["buzzer", ":=", _] | ["ld", "st", _] => 0xF018 | self.register(&tokens[2])? << 8,
+            ["pitch", ":=", _] => 0xF03A | self.register(&tokens[2])? << 8,
+            ["pitch", _] => 0xF03A | x()?,
+            [_, ":=", "delay"] | ["ld", _, "dt"] => 0xF007 | self.register(&tokens[usize::from(words[0] == "ld")])? << 8,
+            [_, ":=", "key"] | ["ld", _, "k"] => 0xF00A | self.register(&tokens[usize::from(words[0] == "ld")])? << 8,
+            [_, ":=", "random", _] => 0xC000 | self.register(&tokens[0])? << 8 | v(3, 0xFF)?,
+            ["rnd", _, _] => 0xC000 | x()? | v(2, 0xFF)?,
+            ["drw" | "sprite", _, _, _] => 0xD000 | xy(2)? | v(3, 0xF)?,
+            ["shr", _] => 0x8006 | xy(1)?,
+            ["shl", _] => 0x800E | xy(1)?,
+            [op, _, _] if alu(op).is_some() => self.arithmetic(op, &tokens[1], &tokens[2])?,
+            [_, op, _] if alu(op).is_some() => self.arithmetic(op, &tokens[0], &tokens[2])?,
+            [_] if self.labels.contains_key(self.resolve(tokens[0].text)) => 0x2000 | v(0, 0xFFF)?,
+            _ => return Err((tokens[0].column, format!("unknown instruction '{}'", tokens.iter().map(|token| token.text).collect::<Vec<_>>().join(" ")))),
+        };
+        Ok(opcode.to_be_bytes().to_vec())
+    }
+}
+
+/// Returns the low nibble of the `8XYN` form of an arithmetic operator, and the
+/// opcode of its `7XNN`-like form taking a byte if it has one.
+fn alu(op: &str) -> Option<(u16, Option<u16>)> {
+    match op {
+        ":=" | "ld" => Some((0x0, Some(0x6000))),
+        "+=" | "add" => Some((0x4, Some(0x7000))),
+        "|=" | "or" => Some((0x1, None)),
+        "&=" | "and" => Some((0x2, None)),
+        "^=" | "xor" => Some((0x3, None)),
+        "-=" | "sub" => Some((0x5, None)),
+        ">>=" | "shr" => Some((0x6, None)),
+        "=-" | "subn" => Some((0x7, None)),
+        "<<=" | "shl" => Some((0xE, None)),
+        _ => None,
+    }
+}
+
+/// Assembles `source` into a program to be loaded at 0x200.
+pub fn assemble(source: &str) -> Result<Vec<u8>, EmuError> {
+    let error = |line: usize, (column, message): LineError| EmuError::Assemble { line: line + 1, column, message };
+    let mut assembler = Assembler { labels: HashMap::new(), aliases: HashMap::new() };
+    let mut statements = Vec::new();
+    let mut address = START;
+
+    for (line, text) in source.lines().enumerate() {
+        let mut tokens = tokenize(text);
+        loop {
+            let label = match tokens.as_slice() {
+                [colon, name, ..] if colon.text == ":" => Some((name.text, name.column, 2)),
+                [name, ..] if name.text.len() > 1 && name.text.ends_with(':') && !name.text.starts_with(':') => {
+                    Some((&name.text[..name.text.len() - 1], name.column, 1))
+                },
+                _ => None,
+            };
+            let Some((name, column, len)) = label else { break };
+            if assembler.labels.insert(name.to_owned(), address).is_some() {
+                return Err(error(line, (column, format!("label '{name}' is already defined"))));
+            }
+            tokens.drain(..len);
+        }
+        match tokens.as_slice() {
+            [] => (),
+            [directive, name, value] if directive.text == ":alias" || directive.text == ":const" => {
+                assembler.aliases.insert(name.text.to_owned(), value.text.to_owned());
+            },
+            [directive, ..] if directive.text.starts_with(':') && directive.text != ":call" => {
+                return Err(error(line, (directive.column, format!("unknown directive '{}'", directive.text))));
+            },
+            _ => {
+                let words: Vec<String> = tokens.iter().map(|token| token.text.to_ascii_lowercase()).collect();
+                address += size(&words);
+                statements.push((line, tokens));
+            },
+        }
+    }
+
+    let mut program = Vec::with_capacity(address - START);
+    for (line, tokens) in statements {
+        program.extend(assembler.encode(&tokens).map_err(|e| error(line, e))?);
+    }
+    if START + program.len() > 0x10000 {
+        return Err(EmuError::RomSize(program.len()));
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::{disassemble, Syntax};
+
+    /// A program using every opcode, with data between and after the code.
+    fn rom() -> Vec<u8> {
+        let code = [
+            0x00E0, 0x00C3, 0x00D2, 0x00FB, 0x00FC, 0x00FE, 0x00FF, 0x2300, 0x3A12, 0x4B34, 0x5120, 0x5122, 0x5123, 0x6C56,
+            0x7D78, 0x8120, 0x8121, 0x8122, 0x8123, 0x8124, 0x8125, 0x8126, 0x8127, 0x812E, 0x9120, 0xA345, 0xC0FF, 0xD125,
+            0xD120, 0xE19E, 0xE2A1, 0x6000, 0xF000, 0xABCD, 0xF201, 0xF002, 0xF107, 0xF20A, 0xF315, 0xF418, 0xF51E, 0xF629,
+            0xF730, 0xF833, 0xF93A, 0xFA55, 0xFB65, 0xFC75, 0xFD85, 0xB266, 0xDEAD, 0x00FD,
+        ];
+        let mut rom: Vec<u8> = code.iter().flat_map(|word: &u16| word.to_be_bytes()).collect();
+        rom.extend([0x12, 0x00, 0xFF, 0x01, 0xA0]);
+        rom.resize(0x100, 0x55);
+        rom.extend([0x60, 0x01, 0x00, 0xEE, 0x99]);
+        rom
+    }
+
+    #[test]
+    fn disassembly_reassembles_to_the_same_bytes() {
+        let rom = rom();
+        for syntax in [Syntax::Octo, Syntax::Classic] {
+            let source = disassemble(&rom, syntax);
+            assert_eq!(assemble(&source).unwrap(), rom, "{syntax} source:\n{source}");
+        }
+    }
+
+    #[test]
+    fn data_is_kept_apart_from_code() {
+        let source = disassemble(&rom(), Syntax::Octo);
+        assert!(source.contains("i := long 0xABCD"), "{source}");
+        assert!(source.contains("0xDE 0xAD"), "{source}");
+        assert!(source.contains(": label_300"), "{source}");
+    }
+
+    fn error(source: &str) -> (usize, usize, String) {
+        match assemble(source) {
+            Err(EmuError::Assemble { line, column, message }) => (line, column, message),
+            other => panic!("expected an assembly error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_line_and_column() {
+        assert_eq!(error("clear\n  foo v1"), (2, 3, "unknown instruction 'foo v1'".to_owned()));
+        assert_eq!(error("v1 := 0x100"), (1, 7, "0x100 does not fit in 8 bits".to_owned()));
+        assert_eq!(error("v1 += vg"), (1, 7, "unknown value 'vg'".to_owned()));
+        assert_eq!(error(": a\nclear\n: a"), (3, 3, "label 'a' is already defined".to_owned()));
+        assert_eq!(error("\t:org 0x300"), (1, 2, "unknown directive ':org'".to_owned()));
+        assert_eq!(error("jump nowhere # comment"), (1, 6, "unknown value 'nowhere'".to_owned()));
+    }
+
+    #[test]
+    fn labels_resolve_forwards_and_backwards() {
+        let program = assemble("start:\n    jump end\nend:\n    jump start").unwrap();
+        assert_eq!(program, [0x12, 0x02, 0x12, 0x00]);
+    }
+}
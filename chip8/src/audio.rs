@@ -0,0 +1,93 @@
+//! The XO-CHIP audio pattern as a stream of samples, shared between the
+//! emulator and an audio thread.
+use std::sync::{
+    atomic::{AtomicU32, AtomicU64, Ordering},
+    Arc,
+};
+
+/// The rate `PatternSource` produces samples at.
+pub const SAMPLE_RATE: u32 = 44100;
+const VOLUME: f32 = 0.5;
+
+/// The XO-CHIP pattern and playback rate, shared between the emulator and the audio thread.
+#[derive(Default)]
+pub struct Voice {
+    pattern: [AtomicU64; 2],
+    rate: AtomicU32,
+}
+
+impl Voice {
+    pub fn set(&self, pattern: &[u8; 0x10], rate: f32) {
+        let (high, low) = pattern.split_at(8);
+        self.pattern[0].store(u64::from_be_bytes(high.try_into().unwrap_or_default()), Ordering::Relaxed);
+        self.pattern[1].store(u64::from_be_bytes(low.try_into().unwrap_or_default()), Ordering::Relaxed);
+        self.rate.store(rate.to_bits(), Ordering::Relaxed);
+    }
+    fn bit(&self, index: usize) -> bool {
+        let word = self.pattern[(index / 64) % 2].load(Ordering::Relaxed);
+        word & (1 << (63 - index % 64)) != 0
+    }
+    fn rate(&self) -> f32 {
+        f32::from_bits(self.rate.load(Ordering::Relaxed))
+    }
+}
+
+/// Loops the 128 sample pattern of a `Voice` as a square wave, picking up
+/// pattern and pitch changes as it plays.
+pub struct PatternSource {
+    voice: Arc<Voice>,
+    position: f32,
+}
+
+impl PatternSource {
+    pub fn new(voice: Arc<Voice>) -> PatternSource {
+        PatternSource { voice, position: 0.0 }
+    }
+}
+
+impl Iterator for PatternSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = if self.voice.bit(self.position as usize) { VOLUME } else { -VOLUME };
+        self.position = (self.position + self.voice.rate() / SAMPLE_RATE as f32) % 128.0;
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Cpu;
+
+    /// The lengths of the first `count` runs of equal samples.
+    fn runs(pitch: u8, count: usize) -> Vec<usize> {
+        let mut cpu = Cpu::new();
+        cpu.pitch = pitch;
+        let voice = Arc::new(Voice::default());
+        voice.set(&[0xFF, 0x00].repeat(8).try_into().unwrap(), cpu.playback_rate());
+        let mut runs: Vec<(f32, usize)> = Vec::new();
+        for sample in PatternSource::new(voice).take(10_000) {
+            match runs.last_mut() {
+                Some((last, len)) if *last == sample => *len += 1,
+                _ => runs.push((sample, 1)),
+            }
+        }
+        assert!(runs.iter().all(|&(sample, _)| sample.abs() == VOLUME));
+        assert_eq!(runs[0].0, VOLUME);
+        runs.iter().take(count).map(|&(_, len)| len).collect()
+    }
+
+    #[test]
+    fn pitch_64_plays_at_4000_hz() {
+        // 8 bits at 4000 bits per second last 88.2 samples
+        assert_eq!(runs(64, 4), [89, 88, 88, 88]);
+    }
+
+    #[test]
+    fn pitch_doubles_every_48_steps() {
+        // 8 bits at 8000 bits per second last 44.1 samples
+        assert_eq!(runs(112, 4), [45, 44, 44, 44]);
+        assert_eq!(runs(16, 2), [177, 176]);
+    }
+}
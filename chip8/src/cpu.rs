@@ -0,0 +1,106 @@
+use crate::font::{FONT, BIGFONT};
+
+pub struct Cpu {
+    pub rom: Vec<u8>,
+    pub memory: [u8; 0x10000],
+    /// One entry per pixel, each bit marking whether the pixel is lit on that plane
+    pub display_buffer: Vec<u8>,
+    pub pc: u16,
+    pub i: u16,
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// The XO-CHIP audio pattern, one bit per sample, played while the sound timer is non-zero
+    pub pattern: [u8; 0x10],
+    /// The XO-CHIP pitch register, setting the pattern playback rate
+    pub pitch: u8,
+    pub v: [u8; 0x10],
+    pub flag: [u8; 0x10],
+    pub keys: [bool; 0x10],
+    pub key_state: bool,
+    pub opcode: u16,
+    pub hires: bool,
+    /// The bit planes affected by drawing, clearing and scrolling
+    pub plane: u8,
+    pub dirty: bool,
+    /// Set by DXYN under the vblank quirk to end the frame early
+    pub vblank_wait: bool,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu::new()
+    }
+}
+
+impl Cpu {
+    pub fn new() -> Cpu {
+        let mut memory = [0; 0x10000];
+        memory[..FONT.len()].copy_from_slice(&FONT);
+        memory[0x50..0x50 + BIGFONT.len()].copy_from_slice(&BIGFONT);
+        Cpu {
+            rom: Vec::new(),
+            memory,
+            display_buffer: vec![0; 0x800],
+            pc: 0x200,
+            i: 0,
+            stack: Vec::new(),
+            delay_timer: 0,
+            sound_timer: 0,
+            pattern: [0xF0; 0x10],
+            pitch: 64,
+            v: [0; 0x10],
+            flag: [0; 0x10],
+            keys: [false; 0x10],
+            key_state: false,
+            opcode: 0x0000,
+            hires: false,
+            plane: 1,
+            dirty: false,
+            vblank_wait: false,
+        }
+    }
+    /// Returns the current display size as (columns, rows).
+    pub fn resolution(&self) -> (usize, usize) {
+        if self.hires { (128, 64) } else { (64, 32) }
+    }
+    /// Returns the pattern playback rate in samples per second, 4000*2^((pitch-64)/48).
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((f32::from(self.pitch) - 64.0) / 48.0)
+    }
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+    pub fn set_flag_register(&mut self, condition: bool) {
+        if condition {
+            self.v[0xF] = 1;
+        } else {
+            self.v[0xF] = 0;
+        }
+    }
+    /// Skips the next instruction, stepping over both words of the four byte `F000 NNNN`.
+    pub fn skip_instruction(&mut self, condition: bool) {
+        if condition {
+            let pc = usize::from(self.pc);
+            let long = self.memory[pc] == 0xF0 && self.memory[(pc + 1) & 0xFFFF] == 0x00;
+            self.pc = self.pc.wrapping_add(if long { 4 } else { 2 });
+        }
+    }
+    /// Scrolls the selected planes by `dx` columns and `dy` rows, clearing the vacated pixels.
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let (cols, rows) = self.resolution();
+        let previous = self.display_buffer.clone();
+        for row in 0..rows {
+            for col in 0..cols {
+                let source = match (col.checked_add_signed(-dx), row.checked_add_signed(-dy)) {
+                    (Some(c), Some(r)) if c < cols && r < rows => previous[r * cols + c] & self.plane,
+                    _ => 0,
+                };
+                let pixel = &mut self.display_buffer[row * cols + col];
+                *pixel = (*pixel & !self.plane) | source;
+            }
+        }
+        self.dirty = true;
+    }
+}
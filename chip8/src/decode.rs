@@ -0,0 +1,383 @@
+use crate::{cpu::Cpu, platform::Platform, quirk::Quirks, rng::Random, EmuError};
+
+pub fn fetch(cpu: &mut Cpu) {
+    let pc = usize::from(cpu.pc);
+    cpu.opcode = u16::from_be_bytes([cpu.memory[pc], cpu.memory[(pc + 1) & 0xFFFF]]);
+    cpu.pc = cpu.pc.wrapping_add(2);
+}
+
+pub fn decode(cpu: &mut Cpu, quirks: &Quirks, rng: &mut dyn Random) -> Result<(), EmuError> {
+    let x = ((cpu.opcode & 0x0F00) >> 8) as usize;
+    let y = ((cpu.opcode & 0x00F0) >> 4) as usize;
+    let xo_chip = quirks.platform == Platform::XoChip;
+    match (cpu.opcode & 0xF000) >> 12 {
+        0x0 => {
+            match cpu.opcode & 0x00FF {
+                0xE0 => {
+                    let plane = cpu.plane;
+                    cpu.display_buffer.iter_mut().for_each(|pixel| *pixel &= !plane);
+                    cpu.dirty = true;
+                },
+                0xEE => cpu.pc = cpu.stack.pop().ok_or(EmuError::Stack("Tried to pop from stack but stack is empty".to_owned()))?,
+                _ if y == 0xC => cpu.scroll(0, (cpu.opcode & 0x000F) as isize),
+                _ if y == 0xD && xo_chip => cpu.scroll(0, -((cpu.opcode & 0x000F) as isize)),
+                0xFB => cpu.scroll(4, 0),
+                0xFC => cpu.scroll(-4, 0),
+                0xFD => {
+                    return Err(EmuError::Exit());
+                },
+                0xFE => {
+                    if cpu.hires {
+                        cpu.hires = false;
+                        cpu.display_buffer.truncate(0x800);
+                        cpu.dirty = true;
+                    }
+                    if xo_chip {
+                        cpu.display_buffer.fill(0);
+                        cpu.dirty = true;
+                    }
+                },
+                0xFF => {
+                    if !cpu.hires {
+                        cpu.hires = true;
+                        cpu.display_buffer.resize(0x2000, 0);
+                        cpu.dirty = true;
+                    }
+                    if xo_chip {
+                        cpu.display_buffer.fill(0);
+                        cpu.dirty = true;
+                    }
+                },
+                _ => return Err(EmuError::Invalid(cpu.opcode)),
+            }
+        },
+        0x1 => cpu.pc = cpu.opcode & 0x0FFF,
+        0x2 => {
+            if cpu.stack.len() <= 16 {
+                cpu.stack.push(cpu.pc);
+                cpu.pc = cpu.opcode & 0x0FFF;
+            } else {
+                return Err(EmuError::Stack("Tried to push to stack but stack is at maximum length".to_owned()));
+            }
+        },
+        0x3 => cpu.skip_instruction(u16::from(cpu.v[x]) == cpu.opcode & 0x00FF),
+        0x4 => cpu.skip_instruction(u16::from(cpu.v[x]) != cpu.opcode & 0x00FF),
+        0x5 => {
+            match cpu.opcode & 0x000F {
+                0x0 => cpu.skip_instruction(cpu.v[x] == cpu.v[y]),
+                0x2 if xo_chip => {
+                    for (offset, reg) in register_range(x, y).enumerate() {
+                        cpu.memory[(usize::from(cpu.i) + offset) & 0xFFFF] = cpu.v[reg];
+                    }
+                },
+                0x3 if xo_chip => {
+                    for (offset, reg) in register_range(x, y).enumerate() {
+                        cpu.v[reg] = cpu.memory[(usize::from(cpu.i) + offset) & 0xFFFF];
+                    }
+                },
+                _ => return Err(EmuError::Invalid(cpu.opcode)),
+            }
+        },
+        0x6 => (cpu.v[x], _) = 0u8.overflowing_add((cpu.opcode & 0x00FF) as u8),
+        0x7 => (cpu.v[x], _) = cpu.v[x].overflowing_add((cpu.opcode & 0x00FF) as u8),
+        0x8 => decode_8(cpu, quirks, x, y)?,
+        0x9 => cpu.skip_instruction(cpu.v[x] != cpu.v[y]),
+        0xA => cpu.i = cpu.opcode & 0x0FFF,
+        0xB => {
+            let i = quirks.jump(cpu, x);
+            cpu.pc = (cpu.opcode & 0x0FFF) + u16::from(i);
+        },
+        0xC => {
+            let random = rng.byte(&cpu.memory);
+            cpu.v[x] = random & (cpu.opcode & 0x00FF) as u8;
+        },
+        0xD => {
+            let (cols, rows) = cpu.resolution();
+            let x = usize::from(cpu.v[x]) % cols;
+            let y = usize::from(cpu.v[y]) % rows;
+            cpu.v[0xF] = 0;
+            match cpu.opcode & 0x000F {
+                0 => draw_sprite(cpu, quirks, x, y, 16, 16),
+                n => draw_sprite(cpu, quirks, x, y, 8, n as usize),
+            }
+            cpu.dirty = true;
+            cpu.vblank_wait = quirks.vblank && !(quirks.platform == Platform::SchipLegacy && cpu.hires);
+        },
+        0xE => {
+            match cpu.opcode & 0x00FF {
+                0x9E => cpu.skip_instruction(cpu.keys[cpu.v[x] as usize]),
+                0xA1 => cpu.skip_instruction(!cpu.keys[cpu.v[x] as usize]),
+                _ => return Err(EmuError::Invalid(cpu.opcode)),
+            }
+        },
+        0xF => decode_f(cpu, quirks, x, xo_chip)?,
+        _ => return Err(EmuError::Invalid(cpu.opcode)),
+    }
+    Ok(())
+}
+
+fn decode_8(cpu: &mut Cpu, quirks: &Quirks, x: usize, y: usize) -> Result<(), EmuError> {
+    match cpu.opcode & 0x000F {
+        0x0 => cpu.v[x] = cpu.v[y],
+        0x1 => {
+            cpu.v[x] |= cpu.v[y];
+            if quirks.logic {
+                cpu.v[0xF] = 0;
+            }
+        },
+        0x2 => {
+            cpu.v[x] &= cpu.v[y];
+            if quirks.logic {
+                cpu.v[0xF] = 0;
+            }
+        },
+        0x3 => {
+            cpu.v[x] ^= cpu.v[y];
+            quirks.logic(cpu);
+        },
+        0x4 => {
+            let (result, carry) = cpu.v[x].overflowing_add(cpu.v[y]);
+            cpu.v[x] = result;
+            cpu.set_flag_register(carry);
+        },
+        0x5 => {
+            let (result, carry) = cpu.v[x].overflowing_sub(cpu.v[y]);
+            cpu.v[x] = result;
+            cpu.set_flag_register(!carry);
+        },
+        0x6 => {
+            quirks.shift(cpu, x, y);
+            let shifted_bit = cpu.v[x] & 1;
+            cpu.v[x] >>= 1;
+            cpu.v[0xF] = shifted_bit;
+        },
+        0x7 => {
+            let (result, carry) = cpu.v[y].overflowing_sub(cpu.v[x]);
+            cpu.v[x] = result;
+            cpu.set_flag_register(!carry);
+        },
+        0xE => {
+            quirks.shift(cpu, x, y);
+            let shifted_bit = cpu.v[x] & 0x80;
+            (cpu.v[x], _) = cpu.v[x].overflowing_shl(1);
+            cpu.v[0xF] = u8::from(shifted_bit != 0);
+        },
+        _ => return Err(EmuError::Invalid(cpu.opcode)),
+    }
+    Ok(())
+}
+
+fn decode_f(cpu: &mut Cpu, quirks: &Quirks, x: usize, xo_chip: bool) -> Result<(), EmuError> {
+    match cpu.opcode & 0x00FF {
+        0x00 if x == 0 && xo_chip => {
+            let pc = usize::from(cpu.pc);
+            cpu.i = u16::from_be_bytes([cpu.memory[pc], cpu.memory[(pc + 1) & 0xFFFF]]);
+            cpu.pc = cpu.pc.wrapping_add(2);
+        },
+        0x01 if xo_chip => cpu.plane = (x & 0x3) as u8,
+        0x02 if x == 0 && xo_chip => {
+            for offset in 0..cpu.pattern.len() {
+                cpu.pattern[offset] = cpu.memory[(usize::from(cpu.i) + offset) & 0xFFFF];
+            }
+        },
+        0x3A if xo_chip => cpu.pitch = cpu.v[x],
+        0x07 => cpu.v[x] = cpu.delay_timer,
+        0x15 => cpu.delay_timer = cpu.v[x],
+        0x18 => cpu.sound_timer = cpu.v[x],
+        0x1E => cpu.i = cpu.i.wrapping_add(u16::from(cpu.v[x])),
+        0x0A => {
+            if let Some(key) = cpu.keys.iter().position(|&x| x) {
+                if !cpu.key_state {
+                    cpu.v[x] = u8::try_from(key)?;
+                    cpu.key_state = true;
+                }
+                cpu.pc = cpu.pc.wrapping_sub(2);
+            } else if cpu.key_state {
+                cpu.key_state = false;
+            } else {
+                cpu.pc = cpu.pc.wrapping_sub(2);
+            }
+        },
+        0x29 => cpu.i = u16::from(cpu.v[x]) * 5,
+        0x30 => cpu.i = u16::from(cpu.v[x]) * 5 + 0x50,
+        0x33 => {
+            let i = usize::from(cpu.i);
+            cpu.memory[i] = cpu.v[x] / 100;
+            cpu.memory[(i + 1) & 0xFFFF] = (cpu.v[x] / 10) % 10;
+            cpu.memory[(i + 2) & 0xFFFF] = cpu.v[x] % 10;
+        },
+        0x55 => {
+            for i in 0..=x { // x+1 cause its vX inclusive
+                cpu.memory[(usize::from(cpu.i) + i) & 0xFFFF] = cpu.v[i];
+            }
+            quirks.memory_increment_by_x(cpu, x)?;
+            quirks.memory_leave_i_unchanged(cpu, x)?;
+        },
+        0x65 => {
+            for i in 0..=x {
+                cpu.v[i] = cpu.memory[(usize::from(cpu.i) + i) & 0xFFFF];
+            }
+            quirks.memory_increment_by_x(cpu, x)?;
+            quirks.memory_leave_i_unchanged(cpu, x)?;
+        },
+        0x75 => {
+            for i in 0..=x {
+                cpu.flag[i] = cpu.v[i];
+            }
+        },
+        0x85 => {
+            for i in 0..=x {
+                cpu.v[i] = cpu.flag[i];
+            }
+        }
+        _ => return Err(EmuError::Invalid(cpu.opcode)),
+    }
+    Ok(())
+}
+
+/// Iterates the registers from `x` to `y` inclusive, in descending order if `x > y`.
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
+
+/// XORs a `width` by `height` sprite at I onto each selected plane, the data for
+/// each plane following the previous one in memory.
+fn draw_sprite(cpu: &mut Cpu, quirks: &Quirks, x: usize, y: usize, width: usize, height: usize) {
+    let (cols, rows) = cpu.resolution();
+    let bytes_per_row = width / 8;
+    let mut addr = usize::from(cpu.i);
+    for plane in [1, 2] {
+        if cpu.plane & plane == 0 {
+            continue;
+        }
+        for row in 0..height {
+            for col in 0..width {
+                let byte = cpu.memory[(addr + row * bytes_per_row + col / 8) & 0xFFFF];
+                if quirks.wrap || ((y + row < rows) && (x + col < cols)) {
+                    let sprite_pixel = byte & (0x80 >> (col % 8));
+                    let screen_pixel = &mut cpu.display_buffer[((y + row) % rows) * cols + (x + col) % cols];
+                    if sprite_pixel != 0 {
+                        if *screen_pixel & plane != 0 {
+                            cpu.v[0xF] = 1;
+                        }
+                        *screen_pixel ^= plane;
+                    }
+                }
+            }
+        }
+        addr += bytes_per_row * height;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Machine, Platform, Quirks};
+
+    /// Loads `rom` on XO-CHIP and executes `steps` instructions.
+    fn run(rom: &[u8], steps: usize) -> Machine {
+        let mut machine = Machine::new(Quirks::for_platform(Platform::XoChip), 1);
+        machine.load_rom(rom).unwrap();
+        step(&mut machine, steps);
+        machine
+    }
+
+    fn step(machine: &mut Machine, steps: usize) {
+        for _ in 0..steps {
+            machine.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn sprites_draw_each_plane_from_consecutive_data() {
+        let mut machine = run(&[
+            0xF3, 0x01, // plane 3
+            0xA2, 0x10, // i := data
+            0xD0, 0x01, // sprite v0 v0 1
+            0xF2, 0x01, // plane 2
+            0xD0, 0x01,
+            0xD0, 0x01,
+            0xF1, 0x01, // plane 1
+            0xD0, 0x01,
+            0x80, 0x40, // data: plane 1, then plane 2
+        ], 3);
+        assert_eq!(machine.cpu.display_buffer[..2], [1, 2]);
+        assert_eq!(machine.cpu.v[0xF], 0);
+        // Plane 2 alone reads the first byte, and doesn't collide with plane 1
+        step(&mut machine, 2);
+        assert_eq!(machine.cpu.display_buffer[..2], [3, 2]);
+        assert_eq!(machine.cpu.v[0xF], 0);
+        step(&mut machine, 1);
+        assert_eq!(machine.cpu.display_buffer[..2], [1, 2]);
+        assert_eq!(machine.cpu.v[0xF], 1);
+        step(&mut machine, 2);
+        assert_eq!(machine.cpu.display_buffer[..2], [0, 2]);
+        assert_eq!(machine.cpu.v[0xF], 1);
+    }
+
+    #[test]
+    fn register_ranges_go_in_either_direction() {
+        let machine = run(&[
+            0x61, 0x01, 0x62, 0x02, 0x63, 0x03,
+            0xA3, 0x00, 0x51, 0x32, // save v1 - v3
+            0xA3, 0x10, 0x53, 0x12, // save v3 - v1
+            0xA3, 0x00, 0x57, 0x63, // load v7 - v6
+        ], 9);
+        assert_eq!(machine.cpu.memory[0x300..0x303], [1, 2, 3]);
+        assert_eq!(machine.cpu.memory[0x310..0x313], [3, 2, 1]);
+        assert_eq!(machine.cpu.v[6..8], [2, 1]);
+        assert_eq!(machine.cpu.i, 0x300);
+    }
+
+    #[test]
+    fn long_loads_are_skipped_whole() {
+        let mut machine = run(&[
+            0xF0, 0x00, 0x12, 0x34, // i := long 0x1234
+            0x30, 0x00, // if v0 != 0 then
+            0xF0, 0x00, 0xAB, 0xCD,
+            0x61, 0x05,
+        ], 1);
+        assert_eq!((machine.cpu.i, machine.cpu.pc), (0x1234, 0x204));
+        step(&mut machine, 1);
+        assert_eq!(machine.cpu.pc, 0x20A);
+        step(&mut machine, 1);
+        assert_eq!((machine.cpu.i, machine.cpu.v[1]), (0x1234, 5));
+    }
+
+    #[test]
+    fn plane_selects_what_clear_and_scroll_touch() {
+        let mut machine = run(&[0xF2, 0x01, 0x00, 0xE0, 0xF3, 0x01, 0x00, 0xD3, 0xF0, 0x01], 0);
+        machine.cpu.display_buffer[5 * 64] = 3;
+        machine.cpu.display_buffer[6 * 64] = 1;
+        step(&mut machine, 2);
+        assert_eq!(machine.cpu.plane, 2);
+        assert_eq!(machine.cpu.display_buffer[5 * 64], 1);
+        assert_eq!(machine.cpu.display_buffer[6 * 64], 1);
+        // Scrolling up 3 moves both rows and clears the bottom
+        step(&mut machine, 2);
+        assert_eq!(machine.cpu.display_buffer[2 * 64], 1);
+        assert_eq!(machine.cpu.display_buffer[3 * 64], 1);
+        assert!(machine.cpu.display_buffer[4 * 64..].iter().all(|&pixel| pixel == 0));
+        step(&mut machine, 1);
+        assert_eq!(machine.cpu.plane, 0);
+    }
+
+    #[test]
+    fn resolution_changes_clear_the_screen() {
+        let mut machine = run(&[0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFE, 0x00, 0xFE], 1);
+        assert!(machine.cpu.hires);
+        assert_eq!(machine.cpu.display_buffer.len(), 0x2000);
+        for _ in 0..3 {
+            machine.cpu.display_buffer[100] = 1;
+            machine.take_dirty();
+            step(&mut machine, 1);
+            assert!(machine.cpu.display_buffer.iter().all(|&pixel| pixel == 0));
+            assert!(machine.take_dirty());
+        }
+        assert!(!machine.cpu.hires);
+        assert_eq!(machine.cpu.display_buffer.len(), 0x800);
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::{self, Write},
+    str::FromStr,
+};
+
+/// The address programs are loaded at.
+const START: u16 = 0x200;
+
+/// The flavour of assembly to produce.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Syntax {
+    /// Octo, e.g. `v1 := 0x20`
+    #[default]
+    Octo,
+    /// The classic Cowgod style, e.g. `LD V1, 0x20`
+    Classic,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "octo" => Ok(Syntax::Octo),
+            "classic" => Ok(Syntax::Classic),
+            _ => Err(format!("unknown syntax '{s}', expected one of: octo, classic")),
+        }
+    }
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Syntax::Octo => "octo",
+            Syntax::Classic => "classic",
+        })
+    }
+}
+
+/// Where execution can continue after an instruction.
+enum Flow {
+    /// The next instruction
+    Next,
+    /// The next instruction, or the one after it
+    Skip,
+    /// Only `target`, as for `1NNN`
+    Jump(u16),
+    /// `target` and then the next instruction, as for `2NNN`
+    Call(u16),
+    /// Somewhere from `target` depending on V0, as for `BNNN`
+    Indexed(u16),
+    /// Nowhere that can be followed statically
+    Stop,
+}
+
+/// Returns the length in bytes of the instruction starting with `opcode`.
+pub fn size(opcode: u16) -> u16 {
+    if opcode == 0xF000 { 4 } else { 2 }
+}
+
+fn flow(opcode: u16) -> Flow {
+    match (opcode & 0xF000) >> 12 {
+        0x0 if opcode == 0x00EE || opcode == 0x00FD => Flow::Stop,
+        0x1 => Flow::Jump(opcode & 0x0FFF),
+        0x2 => Flow::Call(opcode & 0x0FFF),
+        0x3 | 0x4 | 0x9 => Flow::Skip,
+        0x5 if opcode & 0x000F == 0 => Flow::Skip,
+        0xB => Flow::Indexed(opcode & 0x0FFF),
+        0xE => Flow::Skip,
+        _ => Flow::Next,
+    }
+}
+
+/// Returns the mnemonic for `opcode`, or `None` if it isn't a valid instruction.
+/// `long` is the word following it, only used by `F000 NNNN`.
+pub fn instruction(opcode: u16, long: u16, syntax: Syntax) -> Option<String> {
+    mnemonic(opcode, long, syntax, &|addr| format!("{addr:#05X}"))
+}
+
+fn mnemonic(opcode: u16, long: u16, syntax: Syntax, target: &dyn Fn(u16) -> String) -> Option<String> {
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let n = opcode & 0x000F;
+    let nn = opcode & 0x00FF;
+    let nnn = opcode & 0x0FFF;
+    let octo = syntax == Syntax::Octo;
+    let (vx, vy) = if octo { (format!("v{x:x}"), format!("v{y:x}")) } else { (format!("V{x:X}"), format!("V{y:X}")) };
+    let text = match (opcode & 0xF000) >> 12 {
+        0x0 if x == 0 => match nn {
+            0xE0 => if octo { "clear".to_owned() } else { "CLS".to_owned() },
+            0xEE => if octo { "return".to_owned() } else { "RET".to_owned() },
+            0xFB => if octo { "scroll-right".to_owned() } else { "SCR".to_owned() },
+            0xFC => if octo { "scroll-left".to_owned() } else { "SCL".to_owned() },
+            0xFD => if octo { "exit".to_owned() } else { "EXIT".to_owned() },
+            0xFE => if octo { "lores".to_owned() } else { "LOW".to_owned() },
+            0xFF => if octo { "hires".to_owned() } else { "HIGH".to_owned() },
+            _ if y == 0xC => if octo { format!("scroll-down {n}") } else { format!("SCD {n}") },
+            _ if y == 0xD => if octo { format!("scroll-up {n}") } else { format!("SCU {n}") },
+            _ => return None,
+        },
+        0x1 => if octo { format!("jump {}", target(nnn)) } else { format!("JP {}", target(nnn)) },
+        0x2 => if octo { format!(":call {}", target(nnn)) } else { format!("CALL {}", target(nnn)) },
+        0x3 => if octo { format!("if {vx} != {nn:#04X} then") } else { format!("SE {vx}, {nn:#04X}") },
+        0x4 => if octo { format!("if {vx} == {nn:#04X} then") } else { format!("SNE {vx}, {nn:#04X}") },
+        0x5 => match n {
+            0x0 => if octo { format!("if {vx} != {vy} then") } else { format!("SE {vx}, {vy}") },
+            0x2 => if octo { format!("save {vx} - {vy}") } else { format!("SAVE {vx}, {vy}") },
+            0x3 => if octo { format!("load {vx} - {vy}") } else { format!("LOAD {vx}, {vy}") },
+            _ => return None,
+        },
+        0x6 => if octo { format!("{vx} := {nn:#04X}") } else { format!("LD {vx}, {nn:#04X}") },
+        0x7 => if octo { format!("{vx} += {nn:#04X}") } else { format!("ADD {vx}, {nn:#04X}") },
+        0x8 => {
+            let (op, name) = match n {
+                0x0 => (":=", "LD"),
+                0x1 => ("|=", "OR"),
+                0x2 => ("&=", "AND"),
+                0x3 => ("^=", "XOR"),
+                0x4 => ("+=", "ADD"),
+                0x5 => ("-=", "SUB"),
+                0x6 => (">>=", "SHR"),
+                0x7 => ("=-", "SUBN"),
+                0xE => ("<<=", "SHL"),
+                _ => return None,
+            };
+            if octo { format!("{vx} {op} {vy}") } else { format!("{name} {vx}, {vy}") }
+        },
+        0x9 if n == 0 => if octo { format!("if {vx} == {vy} then") } else { format!("SNE {vx}, {vy}") },
+        0xA => if octo { format!("i := {}", target(nnn)) } else { format!("LD I, {}", target(nnn)) },
+        0xB => if octo { format!("jump0 {}", target(nnn)) } else { format!("JP V0, {}", target(nnn)) },
+        0xC => if octo { format!("{vx} := random {nn:#04X}") } else { format!("RND {vx}, {nn:#04X}") },
+        0xD => if octo { format!("sprite {vx} {vy} {n}") } else { format!("DRW {vx}, {vy}, {n}") },
+        0xE => match nn {
+            0x9E => if octo { format!("if {vx} -key then") } else { format!("SKP {vx}") },
+            0xA1 => if octo { format!("if {vx} key then") } else { format!("SKNP {vx}") },
+            _ => return None,
+        },
+        0xF => match nn {
+            0x00 if x == 0 => if octo { format!("i := long {long:#06X}") } else { format!("LD I, LONG {long:#06X}") },
+            0x01 => if octo { format!("plane {x}") } else { format!("PLANE {x}") },
+            0x02 if x == 0 => if octo { "audio".to_owned() } else { "AUDIO".to_owned() },
+            0x07 => if octo { format!("{vx} := delay") } else { format!("LD {vx}, DT") },
+            0x0A => if octo { format!("{vx} := key") } else { format!("LD {vx}, K") },
+            0x15 => if octo { format!("delay := {vx}") } else { format!("LD DT, {vx}") },
+            0x18 => if octo { format!("buzzer := {vx}") } else { format!("LD ST, {vx}") },
+            0x1E => if octo { format!("i += {vx}") } else { format!("ADD I, {vx}") },
+            0x29 => if octo { format!("i := hex {vx}") } else { format!("LD F, {vx}") },
+            0x30 => if octo { format!("i := bighex {vx}") } else { format!("LD HF, {vx}") },
+            0x33 => if octo { format!("bcd {vx}") } else { format!("LD B, {vx}") },
+            0x3A => if octo { format!("pitch := {vx}") } else { format!("PITCH {vx}") },
+            0x55 => if octo { format!("save {vx}") } else { format!("LD [I], {vx}") },
+            0x65 => if octo { format!("load {vx}") } else { format!("LD {vx}, [I]") },
+            0x75 => if octo { format!("saveflags {vx}") } else { format!("LD R, {vx}") },
+            0x85 => if octo { format!("loadflags {vx}") } else { format!("LD {vx}, R") },
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(text)
+}
+
+fn word(rom: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*rom.get(offset)?, *rom.get(offset + 1)?]))
+}
+
+/// Follows every path of execution from the start of the program, returning the
+/// addresses where instructions start and those which are jumped to or called.
+fn traverse(rom: &[u8]) -> (BTreeSet<u16>, BTreeSet<u16>) {
+    let end = usize::from(START) + rom.len();
+    let in_rom = |addr: u16| (usize::from(START)..end).contains(&usize::from(addr));
+    let mut code = BTreeSet::new();
+    let mut targets = BTreeSet::new();
+    let mut pending = vec![START];
+    while let Some(addr) = pending.pop() {
+        if !in_rom(addr) || code.contains(&addr) {
+            continue;
+        }
+        let offset = usize::from(addr - START);
+        let Some(opcode) = word(rom, offset) else { continue };
+        let long = word(rom, offset + 2).unwrap_or(0);
+        if mnemonic(opcode, long, Syntax::Octo, &|_| String::new()).is_none() || (opcode == 0xF000 && offset + 4 > rom.len()) {
+            continue;
+        }
+        code.insert(addr);
+        let next = addr.wrapping_add(size(opcode));
+        match flow(opcode) {
+            Flow::Next => pending.push(next),
+            Flow::Skip => {
+                pending.push(next);
+                let skipped = word(rom, usize::from(next.wrapping_sub(START))).unwrap_or(0);
+                pending.push(next.wrapping_add(size(skipped)));
+            },
+            Flow::Jump(target) => {
+                targets.insert(target);
+                pending.push(target);
+            },
+            Flow::Call(target) => {
+                targets.insert(target);
+                pending.push(target);
+                pending.push(next);
+            },
+            Flow::Indexed(target) => {
+                // Only the base of the jump table can be followed statically
+                targets.insert(target);
+                pending.push(target);
+            },
+            Flow::Stop => (),
+        }
+    }
+    targets.retain(|&addr| in_rom(addr));
+    (code, targets)
+}
+
+/// Disassembles a program loaded at 0x200, separating code from data by following
+/// jumps, calls and skips from the entry point. Bytes never reached are emitted
+/// as data, and jump and call targets get labels.
+pub fn disassemble(rom: &[u8], syntax: Syntax) -> String {
+    let (code, targets) = traverse(rom);
+    let labels: HashMap<u16, String> = targets.iter().map(|&addr| (addr, format!("label_{addr:03X}"))).collect();
+    let target = |addr: u16| labels.get(&addr).cloned().unwrap_or_else(|| format!("{addr:#05X}"));
+    // Code and data may not be split across a label or the start of other code
+    let boundaries: BTreeSet<u16> = code.union(&targets).copied().collect();
+    // Addresses are compared as usize, as an instruction may end past 0xFFFF
+    let is_boundary = |from: usize, to: usize| {
+        u16::try_from(from).is_ok_and(|from| boundaries.range(from..).next().is_some_and(|&addr| usize::from(addr) < to))
+    };
+
+    let mut out = String::new();
+    let mut data = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let addr = START + u16::try_from(offset).unwrap_or(u16::MAX);
+        if labels.contains_key(&addr) || code.contains(&addr) {
+            flush_data(&mut out, &mut data, syntax);
+        }
+        if let Some(label) = labels.get(&addr) {
+            let _ = match syntax {
+                Syntax::Octo => writeln!(out, ": {label}"),
+                Syntax::Classic => writeln!(out, "{label}:"),
+            };
+        }
+        if code.contains(&addr) {
+            let opcode = word(rom, offset).unwrap_or(0);
+            let len = size(opcode);
+            if !is_boundary(usize::from(addr) + 1, usize::from(addr) + usize::from(len)) {
+                let long = word(rom, offset + 2).unwrap_or(0);
+                if let Some(text) = mnemonic(opcode, long, syntax, &target) {
+                    let _ = writeln!(out, "    {text}");
+                    offset += usize::from(len);
+                    continue;
+                }
+            }
+        }
+        data.push(rom[offset]);
+        offset += 1;
+    }
+    flush_data(&mut out, &mut data, syntax);
+    out
+}
+
+fn flush_data(out: &mut String, data: &mut Vec<u8>, syntax: Syntax) {
+    for line in data.chunks(8) {
+        let bytes: Vec<String> = line.iter().map(|byte| format!("{byte:#04X}")).collect();
+        let _ = match syntax {
+            Syntax::Octo => writeln!(out, "    {}", bytes.join(" ")),
+            Syntax::Classic => writeln!(out, "    db {}", bytes.join(", ")),
+        };
+    }
+    data.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn rom_filling_memory() {
+        // Straight-line code up to the last word at 0xFFFE
+        let rom = [0x60, 0x00].repeat(0xFE00 / 2);
+        for syntax in [Syntax::Octo, Syntax::Classic] {
+            assert_eq!(assemble(&disassemble(&rom, syntax)).unwrap(), rom);
+        }
+        // A long load at 0xFFFC, and one cut off by the end of memory
+        let mut rom = [0x60, 0x00].repeat(0xFDFC / 2);
+        rom.extend([0xF0, 0x00, 0x12, 0x34]);
+        assert!(disassemble(&rom, Syntax::Octo).ends_with("i := long 0x1234\n"));
+        rom.truncate(0xFDFE);
+        rom.extend([0xF0, 0x00]);
+        assert_eq!(assemble(&disassemble(&rom, Syntax::Octo)).unwrap(), rom);
+    }
+}
@@ -0,0 +1,27 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum EmuError {
+    #[error("Failed to convert usize to i32 {0}")]
+    IntCast(#[from] std::num::TryFromIntError),
+    #[error("Io Error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Stack Error: {0}")]
+    Stack(String),
+    #[error("ROM is too large to fit in memory ({0} bytes)")]
+    RomSize(usize),
+    #[error("{line}:{column}: {message}")]
+    Assemble { line: usize, column: usize, message: String },
+    #[error("Invalid save state: {0}")]
+    State(String),
+    #[error("Save state version {found} is not supported, expected version {expected}")]
+    StateVersion { found: u16, expected: u16 },
+    #[error("Invalid movie: {0}")]
+    Movie(String),
+    #[error("Program exited")]
+    Exit(),
+    #[error("Hit breakpoint at {0:#05X}")]
+    Breakpoint(u16),
+    #[error("Invalid instruction {0}")]
+    Invalid(u16),
+}
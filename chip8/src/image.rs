@@ -0,0 +1,68 @@
+//! Conversions of the display buffer into formats that can be inspected
+//! without a window, e.g. for headless runs on build machines.
+use crate::{cpu::Cpu, png};
+
+/// RGB colours for pixels lit on no plane, plane 1, plane 2 and both planes.
+pub type Palette = [[u8; 3]; 4];
+
+/// The default palette, matching the frontend's default colours.
+pub const GREYSCALE: Palette = [[0x00; 3], [0xFF; 3], [0xAA; 3], [0x55; 3]];
+
+/// Renders the display as text, one line per row: `.` for unlit pixels,
+/// `#` for plane 1, `+` for plane 2 and `%` for both.
+pub fn ascii(cpu: &Cpu) -> String {
+    let (cols, _) = cpu.resolution();
+    let mut out = String::with_capacity(cpu.display_buffer.len() + cpu.display_buffer.len() / cols);
+    for row in cpu.display_buffer.chunks(cols) {
+        out.extend(row.iter().map(|&pixel| ['.', '#', '+', '%'][usize::from(pixel & 0x3)]));
+        out.push('\n');
+    }
+    out
+}
+
+/// Encodes the display as a greyscale PNG at its native resolution.
+pub fn png(cpu: &Cpu) -> Result<Vec<u8>, crate::EmuError> {
+    screenshot(cpu, 1, &GREYSCALE)
+}
+
+/// Encodes the display as a PNG in `palette`, each pixel drawn as a `scale` by
+/// `scale` square, so lores screens come out half the size of hires ones.
+pub fn screenshot(cpu: &Cpu, scale: usize, palette: &Palette) -> Result<Vec<u8>, crate::EmuError> {
+    let (cols, rows) = cpu.resolution();
+    let scale = scale.max(1);
+    let mut rgb = Vec::with_capacity(cols * rows * scale * scale * 3);
+    for row in cpu.display_buffer.chunks(cols) {
+        let line: Vec<u8> = row
+            .iter()
+            .flat_map(|&pixel| palette[usize::from(pixel & 0x3)].repeat(scale))
+            .collect();
+        for _ in 0..scale {
+            rgb.extend_from_slice(&line);
+        }
+    }
+    Ok(png::encode(u32::try_from(cols * scale)?, u32::try_from(rows * scale)?, &rgb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, Platform, Quirks};
+
+    /// The width and height from the IHDR chunk, which comes first in every PNG.
+    fn size(png: &[u8]) -> (u32, u32) {
+        assert_eq!(png[12..16], *b"IHDR");
+        (u32::from_be_bytes(png[16..20].try_into().unwrap()), u32::from_be_bytes(png[20..24].try_into().unwrap()))
+    }
+
+    #[test]
+    fn screenshots_scale_both_resolutions() {
+        let mut machine = Machine::new(Quirks::for_platform(Platform::SchipModern), 10);
+        assert_eq!(size(&screenshot(&machine.cpu, 3, &GREYSCALE).unwrap()), (64 * 3, 32 * 3));
+        assert_eq!(size(&png(&machine.cpu).unwrap()), (64, 32));
+        // Switch to hires
+        machine.load_rom(&[0x00, 0xFF]).unwrap();
+        machine.step().unwrap();
+        assert_eq!(size(&screenshot(&machine.cpu, 3, &GREYSCALE).unwrap()), (128 * 3, 64 * 3));
+        assert_eq!(size(&screenshot(&machine.cpu, 0, &GREYSCALE).unwrap()), (128, 64));
+    }
+}
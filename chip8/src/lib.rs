@@ -0,0 +1,31 @@
+#![allow(clippy::struct_excessive_bools)]
+pub mod asm;
+pub mod audio;
+mod cpu;
+mod decode;
+pub mod disasm;
+mod error;
+mod font;
+pub mod image;
+mod machine;
+pub mod movie;
+mod platform;
+mod png;
+mod quirk;
+mod reader;
+pub mod rewind;
+mod rng;
+mod state;
+mod timing;
+pub mod video;
+
+pub use crate::{
+    cpu::Cpu,
+    error::EmuError,
+    font::{FONT, BIGFONT},
+    machine::Machine,
+    platform::Platform,
+    quirk::{MemoryQuirk, Quirks},
+    rng::{Generator, Random},
+    timing::Timing,
+};
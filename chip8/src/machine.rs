@@ -0,0 +1,299 @@
+use crate::{
+    cpu::Cpu,
+    decode::{fetch, decode},
+    quirk::Quirks,
+    rng::{self, Generator, Random},
+    state,
+    timing::{self, Timing},
+    EmuError,
+};
+use std::collections::HashSet;
+
+/// A complete CHIP-8 system: the CPU state, the quirks it runs with and the
+/// RNG used by `CXNN`, seeded from the clock unless `set_rng` is called.
+/// Frontends drive it with `run_frame` and poll `take_dirty` and `beeper` to
+/// know when to redraw and when to beep.
+pub struct Machine {
+    pub cpu: Cpu,
+    pub quirks: Quirks,
+    /// Instructions executed per frame under `Timing::Instructions`
+    pub speed: u32,
+    /// Instructions per second under `Timing::Instructions`, replacing `speed`
+    /// when set, so that frames run `ips / 60` instructions on average
+    pub ips: Option<u32>,
+    /// Instructions per second left over from previous frames under `ips`,
+    /// always less than 60
+    pub(crate) ips_carry: u32,
+    pub timing: Timing,
+    /// Under `Timing::Vip`, the cycles left over from the last frame, negative
+    /// when an instruction overran it
+    pub(crate) budget: i64,
+    /// Instructions executed since the machine was created, for speed reports
+    instructions: u64,
+    /// Addresses at which `run_frame` stops before executing the instruction
+    pub breakpoints: HashSet<u16>,
+    /// The instructions left in a frame a breakpoint stopped
+    interrupted: Option<u32>,
+    /// An address whose breakpoint the next `run_frame` passes over
+    skip_breakpoint: Option<u16>,
+    pub(crate) rng: Box<dyn Random>,
+    generator: Generator,
+    seed: u64,
+    /// The second page of the VIP interpreter, kept at 0x100 across reloads
+    interpreter: Option<[u8; 0x100]>,
+}
+
+impl Machine {
+    pub fn new(quirks: Quirks, speed: u32) -> Machine {
+        let seed = rng::random_seed();
+        Machine {
+            cpu: Cpu::new(),
+            quirks,
+            speed,
+            ips: None,
+            ips_carry: 0,
+            timing: Timing::default(),
+            budget: 0,
+            instructions: 0,
+            breakpoints: HashSet::new(),
+            interrupted: None,
+            skip_breakpoint: None,
+            rng: Generator::default().create(seed),
+            generator: Generator::default(),
+            seed,
+            interpreter: None,
+        }
+    }
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), EmuError> {
+        let memory = &mut self.cpu.memory[0x200..];
+        if rom.len() > memory.len() {
+            return Err(EmuError::RomSize(rom.len()));
+        }
+        memory[..rom.len()].copy_from_slice(rom);
+        self.cpu.rom = rom.to_vec();
+        if let Some(page) = &self.interpreter {
+            self.cpu.memory[0x100..0x200].copy_from_slice(page);
+        }
+        Ok(())
+    }
+    /// Places the second page (0x100-0x1FF) of the COSMAC VIP's CHIP-8
+    /// interpreter in memory, where `Generator::Vip` reads it as a table like
+    /// the original routine does. The first page is left to the font.
+    pub fn set_interpreter(&mut self, page: [u8; 0x100]) {
+        self.cpu.memory[0x100..0x200].copy_from_slice(&page);
+        self.interpreter = Some(page);
+    }
+    pub fn interpreter(&self) -> Option<&[u8; 0x100]> {
+        self.interpreter.as_ref()
+    }
+    /// The seed of the `CXNN` generator, which with the generator and the key
+    /// presses of each frame fully determines a run.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    pub fn generator(&self) -> Generator {
+        self.generator
+    }
+    /// Restarts `CXNN` from `seed` with one of the built-in generators.
+    pub fn set_rng(&mut self, generator: Generator, seed: u64) {
+        self.rng = generator.create(seed);
+        self.generator = generator;
+        self.seed = seed;
+    }
+    /// Replaces the `CXNN` generator with a custom one. `seed` and `generator`
+    /// keep describing the previous built-in generator.
+    pub fn set_random(&mut self, rng: Box<dyn Random>) {
+        self.rng = rng;
+    }
+    /// Restarts the loaded ROM from a fresh CPU. A soft reset keeps the RPL flags
+    /// and the generator's state, a hard reset also clears the flags and restarts
+    /// the generator from its seed.
+    pub fn reset(&mut self, hard: bool) -> Result<(), EmuError> {
+        let rom = std::mem::take(&mut self.cpu.rom);
+        let flag = self.cpu.flag;
+        self.cpu = Cpu::new();
+        self.load_rom(&rom)?;
+        if hard {
+            self.set_rng(self.generator, self.seed);
+        } else {
+            self.cpu.flag = flag;
+        }
+        self.cpu.dirty = true;
+        self.interrupted = None;
+        Ok(())
+    }
+    /// The number of instructions executed so far, including by `step`.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+    /// Fetches and executes a single instruction.
+    pub fn step(&mut self) -> Result<(), EmuError> {
+        self.execute().map(|_| ())
+    }
+    /// Ticks the timers once and executes `speed` or `ips / 60` instructions, or a frame's
+    /// worth of VIP cycles, stopping at the first error or with
+    /// `EmuError::Breakpoint` when reaching a breakpoint. Under the vblank quirk
+    /// the frame also ends after the first DXYN, forfeiting any cycles left.
+    ///
+    /// After a breakpoint the next call finishes the interrupted frame, starting
+    /// with the instruction at the breakpoint, rather than running a new one.
+    pub fn run_frame(&mut self) -> Result<(), EmuError> {
+        let mut remaining = match self.interrupted.take() {
+            Some(remaining) => remaining,
+            None => {
+                self.cpu.tick_timers();
+                self.rng.tick();
+                self.cpu.vblank_wait = false;
+                if self.timing == Timing::Vip {
+                    // Cycles carry over so a slow instruction delays the next
+                    // frame's work rather than being free
+                    self.budget = self.budget.min(0) + timing::VIP_BUDGET;
+                }
+                match self.ips {
+                    Some(ips) => {
+                        let due = ips.saturating_add(self.ips_carry);
+                        self.ips_carry = due % 60;
+                        due / 60
+                    },
+                    None => self.speed,
+                }
+            },
+        };
+        let skip = self.skip_breakpoint.take();
+        let mut first = true;
+        loop {
+            match self.timing {
+                Timing::Instructions if remaining == 0 => break,
+                Timing::Vip if self.budget <= 0 => break,
+                _ => (),
+            }
+            let pc = self.cpu.pc;
+            if self.breakpoints.contains(&pc) && !(first && skip == Some(pc)) {
+                self.interrupted = Some(remaining);
+                self.skip_breakpoint = Some(pc);
+                return Err(EmuError::Breakpoint(pc));
+            }
+            first = false;
+            let cycles = self.execute()?;
+            match self.timing {
+                Timing::Instructions => remaining -= 1,
+                Timing::Vip => self.budget -= i64::from(cycles),
+            }
+            if std::mem::take(&mut self.cpu.vblank_wait) {
+                self.budget = self.budget.min(0);
+                break;
+            }
+        }
+        Ok(())
+    }
+    /// Whether a breakpoint stopped the last frame part way through.
+    pub fn mid_frame(&self) -> bool {
+        self.interrupted.is_some()
+    }
+    /// Lets the next `run_frame` execute the instruction at the current address
+    /// even if it has a breakpoint, to continue from one.
+    pub fn skip_breakpoint(&mut self) {
+        self.skip_breakpoint = Some(self.cpu.pc);
+    }
+    /// Executes one instruction, returning its VIP cycle cost.
+    fn execute(&mut self) -> Result<u32, EmuError> {
+        self.instructions += 1;
+        fetch(&mut self.cpu);
+        let cycles = timing::vip_cycles(&self.cpu);
+        decode(&mut self.cpu, &self.quirks, self.rng.as_mut())?;
+        Ok(cycles)
+    }
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        self.cpu.keys[key] = pressed;
+    }
+    /// Returns whether the display changed since the last call, clearing the flag.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.cpu.dirty)
+    }
+    /// Returns whether the beeper should currently be sounding.
+    pub fn beeper(&self) -> bool {
+        self.cpu.sound_timer != 0
+    }
+    /// Serializes the whole machine into the versioned save state format.
+    pub fn save_state(&self) -> Vec<u8> {
+        state::save(self)
+    }
+    /// Restores a state produced by `save_state`, leaving the machine untouched
+    /// if the state is corrupt or from an incompatible version.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), EmuError> {
+        state::load(self, data)?;
+        self.interrupted = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Platform;
+
+    #[test]
+    fn breakpoints_resume_the_interrupted_frame() {
+        for timing in [Timing::Instructions, Timing::Vip] {
+            let mut machine = Machine::new(Quirks::for_platform(Platform::Chip8), 10);
+            machine.timing = timing;
+            // v1 := 60, delay := v1, then count up in v0 forever
+            machine.load_rom(&[0x61, 0x3C, 0xF1, 0x15, 0x70, 0x01, 0x12, 0x04]).unwrap();
+            let mut plain = Machine::new(machine.quirks.clone(), 10);
+            plain.timing = timing;
+            plain.load_rom(&machine.cpu.rom.clone()).unwrap();
+            for _ in 0..2 {
+                plain.run_frame().unwrap();
+            }
+
+            // The loop passes the breakpoint several times a frame
+            machine.breakpoints.insert(0x206);
+            let mut stops = 0;
+            for _ in 0..2 {
+                while let Err(EmuError::Breakpoint(0x206)) = machine.run_frame() {
+                    assert!(machine.mid_frame());
+                    stops += 1;
+                }
+            }
+            assert!(stops > 2);
+            assert_eq!(machine.instructions(), plain.instructions());
+            assert_eq!(machine.cpu.v, plain.cpu.v);
+            assert_eq!(machine.cpu.delay_timer, plain.cpu.delay_timer);
+        }
+    }
+
+    #[test]
+    fn continuing_passes_a_breakpoint_once() {
+        let mut machine = Machine::new(Quirks::for_platform(Platform::Chip8), 10);
+        // Count up in v0 forever
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        machine.breakpoints.insert(0x200);
+        assert!(matches!(machine.run_frame(), Err(EmuError::Breakpoint(0x200))));
+        machine.skip_breakpoint();
+        assert!(matches!(machine.run_frame(), Err(EmuError::Breakpoint(0x200))));
+        assert_eq!(machine.cpu.v[0], 1);
+    }
+
+    #[test]
+    fn ips_spreads_fractional_instructions_over_frames() {
+        let mut machine = Machine::new(Quirks::for_platform(Platform::Chip8), 10);
+        machine.ips = Some(90);
+        // Count up in v0 forever
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let counts: Vec<u64> = (0..60).map(|_| {
+            let before = machine.instructions();
+            machine.run_frame().unwrap();
+            machine.instructions() - before
+        }).collect();
+        assert_eq!(machine.instructions(), 90);
+        assert!(counts.iter().all(|&count| count == 1 || count == 2));
+
+        // Rates too large to add the carry to don't overflow
+        machine.ips = Some(u32::MAX);
+        machine.speed = 0;
+        machine.ips_carry = 59;
+        machine.breakpoints.extend([0x200, 0x202]);
+        assert!(machine.run_frame().is_err());
+        assert_eq!(machine.interrupted, Some(u32::MAX / 60));
+    }
+}
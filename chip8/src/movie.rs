@@ -0,0 +1,227 @@
+//! Movie files: the settings a run started with and the keypad state of every
+//! frame, enough to replay the run bit-exactly.
+use crate::{reader::Reader, EmuError, Generator, Machine, Quirks, Timing};
+
+const MAGIC: &[u8; 4] = b"C8MV";
+/// Bumped whenever the layout below changes.
+const VERSION: u16 = 6;
+
+pub struct Movie {
+    /// The SHA-1 of the ROM the movie was recorded with
+    hash: String,
+    generator: Generator,
+    seed: u64,
+    speed: u32,
+    ips: Option<u32>,
+    timing: Timing,
+    quirks: Quirks,
+    flag: [u8; 0x10],
+    /// The VIP interpreter page read by `--rng vip`, if one was loaded
+    interpreter: Option<[u8; 0x100]>,
+    /// The held keys of each frame, one bit per key
+    frames: Vec<u16>,
+}
+
+impl Movie {
+    /// Starts a recording of `machine`, which should not have run yet.
+    pub fn new(hash: String, machine: &Machine) -> Movie {
+        Movie {
+            hash,
+            generator: machine.generator(),
+            seed: machine.seed(),
+            speed: machine.speed,
+            ips: machine.ips,
+            timing: machine.timing,
+            quirks: machine.quirks.clone(),
+            flag: machine.cpu.flag,
+            interpreter: machine.interpreter().copied(),
+            frames: Vec::new(),
+        }
+    }
+    /// The number of frames recorded.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+    /// Appends the keys currently held on `machine` as the next frame.
+    pub fn record(&mut self, machine: &Machine) {
+        let keys = machine.cpu.keys.iter().enumerate().fold(0, |keys, (key, &held)| keys | u16::from(held) << key);
+        self.frames.push(keys);
+    }
+    /// Drops the last frame, keeping the movie in step with a rewound machine.
+    pub fn unrecord(&mut self) {
+        self.frames.pop();
+    }
+    /// Sets up `machine` to replay the movie, failing if it was recorded with another ROM.
+    pub fn apply(&self, hash: &str, machine: &mut Machine) -> Result<(), EmuError> {
+        if !self.hash.eq_ignore_ascii_case(hash) {
+            return Err(EmuError::Movie(format!("recorded with a ROM with SHA-1 {}, but this ROM has SHA-1 {hash}", self.hash)));
+        }
+        machine.quirks = self.quirks.clone();
+        machine.speed = self.speed;
+        machine.ips = self.ips;
+        machine.timing = self.timing;
+        machine.set_rng(self.generator, self.seed);
+        machine.cpu.flag = self.flag;
+        if let Some(page) = self.interpreter {
+            machine.set_interpreter(page);
+        }
+        Ok(())
+    }
+    /// Sets the keys held on `frame`, returning false once the movie is over.
+    pub fn play(&self, frame: usize, machine: &mut Machine) -> bool {
+        let Some(&keys) = self.frames.get(frame) else {
+            return false;
+        };
+        for key in 0..0x10 {
+            machine.set_key(key, keys & 1 << key != 0);
+        }
+        true
+    }
+    /// Serializes the movie for writing to a file.
+    pub fn save(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(0x80 + self.frames.len() * 2);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        write_str(&mut out, &self.hash);
+        write_str(&mut out, &self.generator.to_string());
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.extend_from_slice(&self.speed.to_le_bytes());
+        // 0 for none, as a rate of 0 would never run anything
+        out.extend_from_slice(&self.ips.unwrap_or(0).to_le_bytes());
+        write_str(&mut out, &self.timing.to_string());
+        write_str(&mut out, &self.quirks.platform.to_string());
+        write_str(&mut out, &self.quirks.memory().to_string());
+        out.extend([self.quirks.shift, self.quirks.wrap, self.quirks.jump, self.quirks.logic, self.quirks.vblank].map(u8::from));
+        out.extend_from_slice(&self.flag);
+        match &self.interpreter {
+            Some(page) => {
+                out.push(1);
+                out.extend_from_slice(page);
+            },
+            None => out.push(0),
+        }
+        for keys in &self.frames {
+            out.extend_from_slice(&keys.to_le_bytes());
+        }
+        out
+    }
+    /// Parses a movie produced by `save`.
+    pub fn load(data: &[u8]) -> Result<Movie, EmuError> {
+        let mut reader = Reader::new(data, EmuError::Movie);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(EmuError::Movie("not a movie file".to_owned()));
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(EmuError::Movie(format!("version {version} is not supported, expected version {VERSION}")));
+        }
+        let hash = reader.string()?;
+        let generator = reader.string()?.parse().map_err(EmuError::Movie)?;
+        let seed = reader.u64()?;
+        let speed = reader.u32()?;
+        let ips = Some(reader.u32()?).filter(|&ips| ips != 0);
+        let timing = reader.string()?.parse().map_err(EmuError::Movie)?;
+        let mut quirks = Quirks::for_platform(reader.string()?.parse().map_err(EmuError::Movie)?);
+        quirks.set_memory(reader.string()?.parse().map_err(EmuError::Movie)?);
+        let [shift, wrap, jump, logic, vblank] = reader.array::<5>()?.map(|b| b != 0);
+        (quirks.shift, quirks.wrap, quirks.jump, quirks.logic, quirks.vblank) = (shift, wrap, jump, logic, vblank);
+        let flag = reader.array()?;
+        let interpreter = match reader.u8()? {
+            0 => None,
+            _ => Some(reader.array()?),
+        };
+        if !reader.data.len().is_multiple_of(2) {
+            return Err(EmuError::Movie("truncated frame".to_owned()));
+        }
+        let frames = reader.data.chunks_exact(2).map(|keys| u16::from_le_bytes([keys[0], keys[1]])).collect();
+        Ok(Movie { hash, generator, seed, speed, ips, timing, quirks, flag, interpreter, frames })
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    out.push(s.len() as u8);
+    out.extend_from_slice(s.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryQuirk, Platform};
+
+    const HASH: &str = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+
+    fn movie() -> Movie {
+        let mut machine = Machine::new(Quirks::for_platform(Platform::SchipLegacy), 15);
+        machine.ips = Some(700);
+        machine.timing = Timing::Vip;
+        machine.quirks.set_memory(MemoryQuirk::Increment);
+        machine.quirks.jump = false;
+        machine.set_rng(Generator::default(), 1234);
+        machine.cpu.flag[3] = 7;
+        machine.set_interpreter([0xA5; 0x100]);
+        let mut movie = Movie::new(HASH.to_owned(), &machine);
+        for frame in 0..20 {
+            machine.set_key(frame % 0x10, frame % 3 == 0);
+            movie.record(&machine);
+        }
+        movie
+    }
+
+    #[test]
+    fn round_trip() {
+        let movie = movie();
+        let loaded = Movie::load(&movie.save()).unwrap();
+        assert_eq!(loaded.hash, movie.hash);
+        assert_eq!(loaded.frames, movie.frames);
+
+        let mut machine = Machine::new(Quirks::for_platform(Platform::Chip8), 1);
+        loaded.apply(&HASH.to_uppercase(), &mut machine).unwrap();
+        assert_eq!((machine.speed, machine.ips, machine.timing), (15, Some(700), Timing::Vip));
+        assert_eq!(machine.quirks.platform, Platform::SchipLegacy);
+        assert_eq!(machine.quirks.memory(), MemoryQuirk::Increment);
+        assert!(!machine.quirks.jump);
+        assert_eq!(machine.seed(), 1234);
+        assert_eq!(machine.cpu.flag[3], 7);
+        assert_eq!(machine.interpreter(), Some(&[0xA5; 0x100]));
+
+        let keys = |machine: &Machine| machine.cpu.keys.iter().enumerate().fold(0, |keys, (key, &held)| keys | u16::from(held) << key);
+        for (frame, &recorded) in movie.frames.iter().enumerate() {
+            assert!(loaded.play(frame, &mut machine));
+            assert_eq!(keys(&machine), recorded);
+        }
+        assert!(!loaded.play(movie.len(), &mut machine));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let data = movie().save();
+        let message = |data: &[u8]| match Movie::load(data) {
+            Err(EmuError::Movie(message)) => message,
+            other => panic!("expected a movie error, got {:?}", other.map(|movie| movie.len())),
+        };
+
+        let mut wrong = data.clone();
+        wrong[0] = b'X';
+        assert_eq!(message(&wrong), "not a movie file");
+
+        let mut wrong = data.clone();
+        wrong[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(message(&wrong).starts_with(&format!("version {}", VERSION + 1)));
+
+        assert_eq!(message(&data[..data.len() - 1]), "truncated frame");
+        assert_eq!(message(&data[..40]), "unexpected end of file");
+    }
+
+    #[test]
+    fn apply_checks_the_rom() {
+        let mut machine = Machine::new(Quirks::for_platform(Platform::Chip8), 1);
+        let other = "0".repeat(40);
+        assert!(matches!(movie().apply(&other, &mut machine), Err(EmuError::Movie(message)) if message.contains(&other)));
+        // Nothing is applied from a movie for another ROM
+        assert_eq!(machine.speed, 1);
+        assert_eq!(machine.quirks.platform, Platform::Chip8);
+    }
+}
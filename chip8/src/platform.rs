@@ -0,0 +1,46 @@
+use std::{fmt, str::FromStr};
+
+/// The CHIP-8 variant being emulated, which decides the default quirks and the
+/// available instruction set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Platform {
+    /// The original COSMAC VIP interpreter
+    Vip,
+    /// CHIP-8 as implemented by most modern interpreters: the VIP's quirks, but
+    /// without waiting for the display interrupt and counting instructions
+    Chip8,
+    /// SUPER-CHIP 1.1 as it ran on the HP 48, waiting for the display interrupt
+    /// before drawing in lores mode
+    SchipLegacy,
+    /// SUPER-CHIP as implemented by most modern interpreters
+    #[default]
+    SchipModern,
+    XoChip,
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vip" => Ok(Platform::Vip),
+            "chip8" | "chip-8" => Ok(Platform::Chip8),
+            "schip-legacy" => Ok(Platform::SchipLegacy),
+            "schip-modern" | "schip" | "superchip" => Ok(Platform::SchipModern),
+            "xo-chip" | "xochip" => Ok(Platform::XoChip),
+            _ => Err(format!("unknown platform '{s}', expected one of: chip8, schip-legacy, schip-modern, xo-chip, vip")),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Platform::Vip => "vip",
+            Platform::Chip8 => "chip8",
+            Platform::SchipLegacy => "schip-legacy",
+            Platform::SchipModern => "schip-modern",
+            Platform::XoChip => "xo-chip",
+        })
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{cpu::Cpu, platform::Platform, EmuError};
+use std::{fmt, str::FromStr};
+
+/// How `FX55` and `FX65` leave I after accessing memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryQuirk {
+    /// I is incremented by X + 1, as on the COSMAC VIP
+    Increment,
+    /// I is incremented by X, as in SUPER-CHIP 1.0
+    IncrementByX,
+    /// I is left unchanged, as in SUPER-CHIP 1.1
+    Unchanged,
+}
+
+impl FromStr for MemoryQuirk {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "increment" => Ok(MemoryQuirk::Increment),
+            "increment-x" => Ok(MemoryQuirk::IncrementByX),
+            "unchanged" => Ok(MemoryQuirk::Unchanged),
+            _ => Err(format!("unknown memory quirk '{s}', expected one of: increment, increment-x, unchanged")),
+        }
+    }
+}
+
+impl fmt::Display for MemoryQuirk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MemoryQuirk::Increment => "increment",
+            MemoryQuirk::IncrementByX => "increment-x",
+            MemoryQuirk::Unchanged => "unchanged",
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Quirks {
+    pub platform: Platform,
+    pub shift: bool,
+    pub memory_increment_by_x: bool,
+    pub memory_leave_i_unchanged: bool,
+    pub wrap: bool,
+    pub jump: bool,
+    pub logic: bool,
+    /// Wait for the display interrupt before drawing, limiting DXYN to one per frame
+    /// as on the VIP. SUPER-CHIP 1.1 only waits in lores mode.
+    pub vblank: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::new()
+    }
+}
+
+impl Quirks {
+    pub fn new() -> Quirks {
+        Quirks {
+            platform: Platform::SchipModern,
+            shift: true,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: true,
+            wrap: false,
+            jump: true,
+            logic: false,
+            vblank: false,
+        }
+    }
+    pub fn for_platform(platform: Platform) -> Quirks {
+        match platform {
+            Platform::Vip | Platform::Chip8 => Quirks {
+                platform,
+                shift: false,
+                memory_increment_by_x: false,
+                memory_leave_i_unchanged: false,
+                wrap: false,
+                jump: false,
+                logic: true,
+                vblank: platform == Platform::Vip,
+            },
+            Platform::SchipLegacy => Quirks {
+                platform,
+                vblank: true,
+                ..Quirks::new()
+            },
+            Platform::SchipModern => Quirks::new(),
+            Platform::XoChip => Quirks {
+                platform,
+                shift: false,
+                memory_increment_by_x: false,
+                memory_leave_i_unchanged: false,
+                wrap: true,
+                jump: false,
+                logic: false,
+                vblank: false,
+            },
+        }
+    }
+    pub fn memory(&self) -> MemoryQuirk {
+        match (self.memory_increment_by_x, self.memory_leave_i_unchanged) {
+            (_, true) => MemoryQuirk::Unchanged,
+            (true, false) => MemoryQuirk::IncrementByX,
+            (false, false) => MemoryQuirk::Increment,
+        }
+    }
+    pub fn set_memory(&mut self, memory: MemoryQuirk) {
+        (self.memory_increment_by_x, self.memory_leave_i_unchanged) = match memory {
+            MemoryQuirk::Increment => (false, false),
+            MemoryQuirk::IncrementByX => (true, false),
+            MemoryQuirk::Unchanged => (false, true),
+        };
+    }
+    pub fn shift(&self, cpu: &mut Cpu, x: usize, y: usize) {
+        if !self.shift {
+            cpu.v[x] = cpu.v[y];
+        }
+    }
+    pub fn memory_increment_by_x(&self, cpu: &mut Cpu, x: usize) -> Result<(), EmuError> {
+        if self.memory_increment_by_x && !self.memory_leave_i_unchanged {
+            cpu.i = cpu.i.wrapping_add(u16::try_from(x)?);
+        }
+        Ok(())
+    }
+    pub fn memory_leave_i_unchanged(&self, cpu: &mut Cpu, x: usize) -> Result<(), EmuError> {
+        if !self.memory_increment_by_x && !self.memory_leave_i_unchanged {
+            cpu.i = cpu.i.wrapping_add(u16::try_from(x)? + 1);
+        }
+        Ok(())
+    }
+    pub fn jump(&self, cpu: &mut Cpu, x: usize) -> u8 {
+        if self.jump {
+            cpu.v[x]
+        } else {
+            cpu.v[0]
+        }
+    }
+    pub fn logic(&self, cpu: &mut Cpu) {
+        if self.logic {
+            cpu.v[0xF] = 0;
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! A cursor over the little endian binary formats of save states and movies.
+use crate::EmuError;
+
+pub(crate) struct Reader<'a> {
+    pub data: &'a [u8],
+    /// Wraps a message in the error variant of the format being read
+    error: fn(String) -> EmuError,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8], error: fn(String) -> EmuError) -> Reader<'a> {
+        Reader { data, error }
+    }
+    fn error(&self, message: impl Into<String>) -> EmuError {
+        (self.error)(message.into())
+    }
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], EmuError> {
+        if self.data.len() < len {
+            return Err(self.error("unexpected end of file"));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], EmuError> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.take(N)?);
+        Ok(bytes)
+    }
+    pub fn u8(&mut self) -> Result<u8, EmuError> {
+        Ok(self.take(1)?[0])
+    }
+    pub fn bool(&mut self) -> Result<bool, EmuError> {
+        Ok(self.u8()? != 0)
+    }
+    pub fn u16(&mut self) -> Result<u16, EmuError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+    pub fn u32(&mut self) -> Result<u32, EmuError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+    pub fn u64(&mut self) -> Result<u64, EmuError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+    /// A string of up to 255 bytes, prefixed with its length.
+    pub fn string(&mut self) -> Result<String, EmuError> {
+        let len = self.u8()?;
+        String::from_utf8(self.take(usize::from(len))?.to_vec()).map_err(|_| self.error("invalid string"))
+    }
+}
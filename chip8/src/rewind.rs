@@ -0,0 +1,147 @@
+//! A ring buffer of per-frame save states for running games backwards. Every
+//! `KEYFRAME_INTERVAL`th state is kept whole and the rest only as the byte
+//! ranges that differ from it, which keeps a minute of history to a few MB.
+use crate::{machine::Machine, EmuError};
+use std::collections::VecDeque;
+
+const KEYFRAME_INTERVAL: usize = 60;
+
+/// A full state and the frames that followed it, stored as deltas against it.
+struct Group {
+    keyframe: Vec<u8>,
+    deltas: Vec<Vec<u8>>,
+}
+
+pub struct Rewind {
+    groups: VecDeque<Group>,
+    capacity: usize,
+    len: usize,
+}
+
+impl Rewind {
+    /// Creates a buffer holding at least the last `frames` frames.
+    pub fn new(frames: usize) -> Rewind {
+        Rewind { groups: VecDeque::new(), capacity: frames, len: 0 }
+    }
+    /// The number of frames that can currently be rewound.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Records the current state of `machine`, discarding the oldest group of
+    /// frames once the buffer holds more than its capacity.
+    pub fn push(&mut self, machine: &Machine) {
+        let state = machine.save_state();
+        match self.groups.back_mut() {
+            Some(group) if group.deltas.len() + 1 < KEYFRAME_INTERVAL => {
+                let delta = diff(&group.keyframe, &state);
+                group.deltas.push(delta);
+            },
+            _ => self.groups.push_back(Group { keyframe: state, deltas: Vec::new() }),
+        }
+        self.len += 1;
+        if let Some(oldest) = self.groups.front() {
+            let size = oldest.deltas.len() + 1;
+            if self.len - size >= self.capacity {
+                self.groups.pop_front();
+                self.len -= size;
+            }
+        }
+    }
+    /// Restores the most recently recorded frame and removes it from the buffer,
+    /// keeping the keys currently held. Returns false once the buffer is empty.
+    pub fn rewind(&mut self, machine: &mut Machine) -> Result<bool, EmuError> {
+        let Some(group) = self.groups.back_mut() else {
+            return Ok(false);
+        };
+        let state = match group.deltas.pop() {
+            Some(delta) => patch(&group.keyframe, &delta),
+            None => self.groups.pop_back().map(|group| group.keyframe).unwrap_or_default(),
+        };
+        self.len -= 1;
+        let keys = machine.cpu.keys;
+        machine.load_state(&state)?;
+        machine.cpu.keys = keys;
+        Ok(true)
+    }
+}
+
+/// Encodes `state` as its length followed by (offset, length, bytes) runs
+/// wherever it differs from `base`.
+fn diff(base: &[u8], state: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(state.len() as u32).to_le_bytes());
+    let differs = |i: usize| base.get(i) != Some(&state[i]);
+    let mut i = 0;
+    while i < state.len() {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < state.len() && differs(i) {
+            i += 1;
+        }
+        out.extend_from_slice(&(start as u32).to_le_bytes());
+        out.extend_from_slice(&((i - start) as u32).to_le_bytes());
+        out.extend_from_slice(&state[start..i]);
+    }
+    out
+}
+
+/// Reverses `diff`, rebuilding the state from `base` and its delta.
+fn patch(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let word = |at: usize| u32::from_le_bytes([delta[at], delta[at + 1], delta[at + 2], delta[at + 3]]) as usize;
+    let mut state = base.to_vec();
+    state.resize(word(0), 0);
+    let mut at = 4;
+    while at < delta.len() {
+        let (start, len) = (word(at), word(at + 4));
+        at += 8;
+        state[start..start + len].copy_from_slice(&delta[at..at + len]);
+        at += len;
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Platform, Quirks};
+
+    #[test]
+    fn patch_undoes_diff() {
+        let lores: Vec<u8> = (0..0x900).map(|i| (i % 7) as u8).collect();
+        let mut hires = lores.clone();
+        hires[3] = 0xFF;
+        hires[0x100..0x180].fill(0xAA);
+        hires.resize(0x2100, 0x11);
+        for (base, state) in [(&lores, &hires), (&hires, &lores), (&lores, &lores), (&Vec::new(), &lores)] {
+            assert_eq!(&patch(base, &diff(base, state)), state);
+        }
+    }
+
+    #[test]
+    fn rewinds_frames_in_reverse() {
+        let mut machine = Machine::new(Quirks::for_platform(Platform::Chip8), 1);
+        // Count up in v0 forever
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut rewind = Rewind::new(100);
+        let mut states = Vec::new();
+        for _ in 0..150 {
+            rewind.push(&machine);
+            states.push(machine.save_state());
+            machine.run_frame().unwrap();
+        }
+        assert!(rewind.len() >= 100);
+        let len = rewind.len();
+        for state in states.iter().rev().take(len) {
+            assert!(rewind.rewind(&mut machine).unwrap());
+            assert_eq!(&machine.save_state(), state);
+        }
+        assert!(!rewind.rewind(&mut machine).unwrap());
+        assert!(rewind.is_empty());
+    }
+}
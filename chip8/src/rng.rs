@@ -0,0 +1,154 @@
+use frand::Rand;
+use std::{fmt, str::FromStr};
+
+/// A source of the random bytes read by `CXNN`. Its whole state must fit in a
+/// `u64` so that save states and movies can capture it.
+pub trait Random {
+    /// Returns the next random byte. `memory` is the machine's memory, which
+    /// generators modelled on real interpreters may read from.
+    fn byte(&mut self, memory: &[u8]) -> u8;
+    /// Called on every 60 Hz timer tick.
+    fn tick(&mut self) {}
+    fn state(&self) -> u64;
+    fn set_state(&mut self, state: u64);
+}
+
+/// The built-in generators, selectable by name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Generator {
+    /// A counter hashed with frand, independent of timing
+    #[default]
+    Frand,
+    /// The COSMAC VIP interpreter's routine, see `VipRandom`
+    Vip,
+}
+
+impl Generator {
+    /// Creates the generator, starting from `seed`.
+    pub fn create(self, seed: u64) -> Box<dyn Random> {
+        match self {
+            Generator::Frand => Box::new(FrandRandom { counter: seed }),
+            Generator::Vip => Box::new(VipRandom { r9: seed as u16 }),
+        }
+    }
+}
+
+impl FromStr for Generator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "frand" => Ok(Generator::Frand),
+            "vip" => Ok(Generator::Vip),
+            _ => Err(format!("unknown random number generator '{s}', expected one of: frand, vip")),
+        }
+    }
+}
+
+impl fmt::Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Generator::Frand => "frand",
+            Generator::Vip => "vip",
+        })
+    }
+}
+
+/// Returns a seed from the system time, for runs that don't ask for one.
+pub(crate) fn random_seed() -> u64 {
+    Rand::new().r#gen()
+}
+
+/// Hashes a counter that advances by the golden ratio on every byte, so the
+/// state is a single word and restoring it is instant.
+struct FrandRandom {
+    counter: u64,
+}
+
+impl Random for FrandRandom {
+    fn byte(&mut self, _memory: &[u8]) -> u8 {
+        self.counter = self.counter.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        Rand::with_seed(self.counter).r#gen()
+    }
+    fn state(&self) -> u64 {
+        self.counter
+    }
+    fn set_state(&mut self, state: u64) {
+        self.counter = state;
+    }
+}
+
+/// The VIP interpreter keeps its seed in register R9, which the display
+/// interrupt increments every frame. `CXNN` increments it again, adds the byte
+/// of the interpreter page (0x100-0x1FF) that the high half points at to the low
+/// half, and keeps the sum as both the new high half and the random byte.
+///
+/// On a real VIP that page holds the interpreter itself, so sequences only
+/// match hardware once `Machine::set_interpreter` has put it there; until then
+/// the page is zeroed and the bytes just count up.
+struct VipRandom {
+    r9: u16,
+}
+
+impl Random for VipRandom {
+    fn byte(&mut self, memory: &[u8]) -> u8 {
+        self.r9 = self.r9.wrapping_add(1);
+        let [high, low] = self.r9.to_be_bytes();
+        let value = low.wrapping_add(memory[0x100 | usize::from(high)]);
+        self.r9 = u16::from_be_bytes([value, low]);
+        value
+    }
+    fn tick(&mut self) {
+        self.r9 = self.r9.wrapping_add(1);
+    }
+    fn state(&self) -> u64 {
+        u64::from(self.r9)
+    }
+    fn set_state(&mut self, state: u64) {
+        self.r9 = state as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(generator: Generator, seed: u64, memory: &[u8]) -> Vec<u8> {
+        let mut rng = generator.create(seed);
+        (0..8).map(|_| rng.byte(memory)).collect()
+    }
+
+    #[test]
+    fn vip_reads_the_interpreter_page() {
+        let mut memory = vec![0; 0x1000];
+        for (i, byte) in memory[0x100..0x200].iter_mut().enumerate() {
+            *byte = (i * 37 + 11) as u8;
+        }
+        assert_eq!(bytes(Generator::Vip, 0x1234, &memory), [0xDA, 0xC3, 0x71, 0x98, 0x3C, 0xF1, 0x1B, 0x2E]);
+    }
+
+    #[test]
+    fn vip_counts_with_an_empty_page() {
+        assert_eq!(bytes(Generator::Vip, 0x1234, &[0; 0x1000]), [0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x3B, 0x3C]);
+    }
+
+    #[test]
+    fn tick_advances_vip() {
+        let mut rng = Generator::Vip.create(0x1234);
+        rng.tick();
+        assert_eq!(rng.state(), 0x1235);
+    }
+
+    #[test]
+    fn seeded_generators_repeat() {
+        for generator in [Generator::Frand, Generator::Vip] {
+            assert_eq!(bytes(generator, 7, &[0; 0x1000]), bytes(generator, 7, &[0; 0x1000]));
+        }
+        let mut rng = Generator::Frand.create(7);
+        rng.byte(&[]);
+        let state = rng.state();
+        let next = rng.byte(&[]);
+        rng.set_state(state);
+        assert_eq!(rng.byte(&[]), next);
+    }
+}
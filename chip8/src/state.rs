@@ -0,0 +1,175 @@
+//! The save state format: a magic number and version followed by every piece
+//! of machine state in a fixed order, multi-byte values little endian.
+use crate::{cpu::Cpu, machine::Machine, platform::Platform, quirk::Quirks, reader::Reader, EmuError};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+/// Bumped whenever the layout below changes.
+const VERSION: u16 = 5;
+/// Platforms in the order of their tag byte.
+const PLATFORMS: [Platform; 5] = [Platform::Vip, Platform::Chip8, Platform::SchipLegacy, Platform::SchipModern, Platform::XoChip];
+
+pub(crate) fn save(machine: &Machine) -> Vec<u8> {
+    let cpu = &machine.cpu;
+    let quirks = &machine.quirks;
+    let mut out = Vec::with_capacity(cpu.memory.len() + cpu.display_buffer.len() + 0x100);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+
+    out.extend_from_slice(&cpu.memory);
+    out.extend_from_slice(&cpu.v);
+    out.extend_from_slice(&cpu.i.to_le_bytes());
+    out.extend_from_slice(&cpu.pc.to_le_bytes());
+    out.push(cpu.stack.len() as u8);
+    for addr in &cpu.stack {
+        out.extend_from_slice(&addr.to_le_bytes());
+    }
+    out.push(cpu.delay_timer);
+    out.push(cpu.sound_timer);
+    out.push(u8::from(cpu.hires));
+    out.push(cpu.plane);
+    out.extend_from_slice(&cpu.display_buffer);
+    out.extend_from_slice(&cpu.pattern);
+    out.push(cpu.pitch);
+    out.extend(cpu.keys.iter().map(|&key| u8::from(key)));
+    out.push(u8::from(cpu.key_state));
+    out.extend_from_slice(&cpu.flag);
+
+    out.push(PLATFORMS.iter().position(|&p| p == quirks.platform).unwrap_or_default() as u8);
+    out.extend([
+        quirks.shift,
+        quirks.memory_increment_by_x,
+        quirks.memory_leave_i_unchanged,
+        quirks.wrap,
+        quirks.jump,
+        quirks.logic,
+        quirks.vblank,
+    ].map(u8::from));
+
+    out.extend_from_slice(&machine.rng.state().to_le_bytes());
+    out.extend_from_slice(&machine.budget.to_le_bytes());
+    out.extend_from_slice(&machine.ips_carry.to_le_bytes());
+    out
+}
+
+pub(crate) fn load(machine: &mut Machine, data: &[u8]) -> Result<(), EmuError> {
+    let mut reader = Reader::new(data, EmuError::State);
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(EmuError::State("not a save state".to_owned()));
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(EmuError::StateVersion { found: version, expected: VERSION });
+    }
+
+    let mut cpu = Cpu::new();
+    cpu.memory.copy_from_slice(reader.take(0x10000)?);
+    cpu.v.copy_from_slice(reader.take(0x10)?);
+    cpu.i = reader.u16()?;
+    cpu.pc = reader.u16()?;
+    let depth = reader.u8()?;
+    for _ in 0..depth {
+        cpu.stack.push(reader.u16()?);
+    }
+    cpu.delay_timer = reader.u8()?;
+    cpu.sound_timer = reader.u8()?;
+    cpu.hires = reader.bool()?;
+    cpu.plane = reader.u8()?;
+    let (cols, rows) = cpu.resolution();
+    cpu.display_buffer = reader.take(cols * rows)?.to_vec();
+    cpu.pattern.copy_from_slice(reader.take(0x10)?);
+    cpu.pitch = reader.u8()?;
+    for key in &mut cpu.keys {
+        *key = reader.bool()?;
+    }
+    cpu.key_state = reader.bool()?;
+    cpu.flag.copy_from_slice(reader.take(0x10)?);
+    cpu.rom.clone_from(&machine.cpu.rom);
+    cpu.dirty = true;
+
+    let platform = *PLATFORMS
+        .get(usize::from(reader.u8()?))
+        .ok_or_else(|| EmuError::State("unknown platform".to_owned()))?;
+    let quirks = Quirks {
+        platform,
+        shift: reader.bool()?,
+        memory_increment_by_x: reader.bool()?,
+        memory_leave_i_unchanged: reader.bool()?,
+        wrap: reader.bool()?,
+        jump: reader.bool()?,
+        logic: reader.bool()?,
+        vblank: reader.bool()?,
+    };
+
+    let rng = reader.u64()?;
+    let budget = reader.u64()? as i64;
+    let ips_carry = reader.u32()?;
+    if !reader.data.is_empty() {
+        return Err(EmuError::State(format!("{} unexpected trailing bytes", reader.data.len())));
+    }
+
+    machine.cpu = cpu;
+    machine.quirks = quirks;
+    machine.rng.set_state(rng);
+    machine.budget = budget;
+    machine.ips_carry = ips_carry;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Machine, Platform, Quirks, EmuError};
+
+    fn machine() -> Machine {
+        let mut machine = Machine::new(Quirks::for_platform(Platform::XoChip), 20);
+        // Draw the font in hires and call a subroutine, so the display and stack are in use
+        machine.load_rom(&[0x00, 0xFF, 0x60, 0x07, 0xF0, 0x29, 0xD0, 0x05, 0x22, 0x0C, 0x12, 0x0A, 0xC1, 0xFF, 0x12, 0x0C]).unwrap();
+        machine.run_frame().unwrap();
+        machine
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut machine = machine();
+        let state = machine.save_state();
+        let (pc, display) = (machine.cpu.pc, machine.cpu.display_buffer.clone());
+        for _ in 0..3 {
+            machine.run_frame().unwrap();
+        }
+        machine.cpu.hires = false;
+        machine.cpu.display_buffer.truncate(0x800);
+        machine.load_state(&state).unwrap();
+        assert_eq!(machine.cpu.pc, pc);
+        assert_eq!(machine.cpu.display_buffer, display);
+        assert_eq!(machine.cpu.stack.len(), 1);
+        assert_eq!(machine.save_state(), state);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut machine = machine();
+        let mut state = machine.save_state();
+        state[4..6].copy_from_slice(&1u16.to_le_bytes());
+        assert!(matches!(machine.load_state(&state), Err(EmuError::StateVersion { found: 1, expected: super::VERSION })));
+    }
+
+    #[test]
+    fn bad_data_leaves_the_machine_untouched() {
+        let mut machine = machine();
+        let state = machine.save_state();
+        machine.run_frame().unwrap();
+        let before = machine.save_state();
+        let mut trailing = state.clone();
+        trailing.push(0);
+        let mut magic = state.clone();
+        magic[0] = b'X';
+        for (data, message) in [
+            (&state[..state.len() - 1], "Invalid save state: unexpected end of file"),
+            (&state[..10], "Invalid save state: unexpected end of file"),
+            (&trailing[..], "Invalid save state: 1 unexpected trailing bytes"),
+            (&magic[..], "Invalid save state: not a save state"),
+        ] {
+            assert_eq!(machine.load_state(data).unwrap_err().to_string(), message);
+            assert_eq!(machine.save_state(), before);
+        }
+    }
+}
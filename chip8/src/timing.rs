@@ -0,0 +1,137 @@
+use crate::cpu::Cpu;
+use std::{fmt, str::FromStr};
+
+/// The 1802 machine cycles in a 60 Hz frame at the VIP's 1.7609 MHz clock,
+/// 8 clock pulses per cycle.
+const FRAME_CYCLES: i64 = 3668;
+/// Cycles per frame taken by the display: the CDP1861 fetches 8 bytes by DMA on
+/// each of its 128 lines, and the interrupt routine that sets it up.
+const DISPLAY_CYCLES: i64 = 1024 + 46;
+/// The cycles left to the interpreter each frame.
+pub(crate) const VIP_BUDGET: i64 = FRAME_CYCLES - DISPLAY_CYCLES;
+/// The cycles the VIP interpreter spends fetching and dispatching any instruction.
+const FETCH_CYCLES: u32 = 40;
+
+/// How `run_frame` decides how much to execute.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timing {
+    /// A fixed number of instructions per frame, `Machine::speed`
+    #[default]
+    Instructions,
+    /// Each instruction is charged its cost on the COSMAC VIP against the
+    /// cycles available per frame, see `vip_cycles`
+    Vip,
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "instructions" => Ok(Timing::Instructions),
+            "vip" => Ok(Timing::Vip),
+            _ => Err(format!("unknown timing '{s}', expected one of: instructions, vip")),
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Timing::Instructions => "instructions",
+            Timing::Vip => "vip",
+        })
+    }
+}
+
+/// Approximates the machine cycles the VIP interpreter takes for the fetched
+/// instruction in `cpu.opcode`, including fetch and dispatch. Sprite drawing is
+/// charged per row, more when the sprite straddles a byte boundary and has to
+/// be shifted, and a screen clear loops over all 256 bytes of display memory.
+pub(crate) fn vip_cycles(cpu: &Cpu) -> u32 {
+    let x = usize::from((cpu.opcode & 0x0F00) >> 8);
+    let n = u32::from(cpu.opcode & 0x000F);
+    let execute = match cpu.opcode & 0xF000 {
+        0x0000 => match cpu.opcode {
+            0x00E0 => 3078,
+            0x00EE => 10,
+            _ => 0,
+        },
+        0x1000 => 12,
+        0x2000 => 26,
+        0x3000 | 0x4000 => 10,
+        0x5000 | 0x9000 => 14,
+        0x6000 => 6,
+        0x7000 => 10,
+        0x8000 => 44,
+        0xA000 => 12,
+        0xB000 => 22,
+        0xC000 => 36,
+        0xD000 => {
+            let shifted = !cpu.v[x].is_multiple_of(8);
+            26 + n * if shifted { 46 } else { 24 }
+        },
+        0xE000 => 14,
+        _ => match cpu.opcode & 0x00FF {
+            0x07 | 0x15 | 0x18 => 10,
+            0x0A => 18,
+            0x1E | 0x29 => 16,
+            0x33 => 84 + 16 * u32::from(cpu.v[x] / 100 + cpu.v[x] / 10 % 10 + cpu.v[x] % 10),
+            0x55 | 0x65 => 14 + 14 * (x as u32 + 1),
+            _ => 0,
+        },
+    };
+    FETCH_CYCLES + execute
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, Platform, Quirks};
+
+    fn machine(rom: &[u8]) -> Machine {
+        let mut machine = Machine::new(Quirks::for_platform(Platform::Vip), 1);
+        machine.timing = Timing::Vip;
+        machine.load_rom(rom).unwrap();
+        machine
+    }
+
+    fn frame(machine: &mut Machine) -> u64 {
+        let before = machine.instructions();
+        machine.run_frame().unwrap();
+        machine.instructions() - before
+    }
+
+    #[test]
+    fn loads_fill_a_frame_and_carry_the_overrun() {
+        // v0 := 0 over and over, 46 cycles each
+        let mut machine = machine(&[0x60, 0x00].repeat(0x200));
+        // 57 loads leave the budget 24 cycles short, so the next frame fits only 56
+        assert_eq!(frame(&mut machine), 57);
+        assert_eq!(machine.budget, VIP_BUDGET - 57 * 46);
+        assert_eq!(frame(&mut machine), 56);
+        assert_eq!(machine.budget, VIP_BUDGET - 57 * 46 + VIP_BUDGET - 56 * 46);
+    }
+
+    #[test]
+    fn sprites_end_the_frame_with_vblank() {
+        // Draw a 5 row sprite at (0, 0) forever
+        let mut machine = machine(&[0xD0, 0x05, 0x12, 0x00]);
+        assert!(machine.quirks.vblank);
+        assert_eq!(frame(&mut machine), 1);
+        // The unused cycles are dropped rather than carried over
+        assert_eq!(machine.budget, 0);
+        // The jump back and the next sprite
+        assert_eq!(frame(&mut machine), 2);
+        assert_eq!(machine.budget, 0);
+    }
+
+    #[test]
+    fn shifted_sprites_cost_more_per_row() {
+        let mut cpu = Cpu::new();
+        cpu.opcode = 0xD015;
+        assert_eq!(vip_cycles(&cpu), FETCH_CYCLES + 26 + 5 * 24);
+        cpu.v[0] = 3;
+        assert_eq!(vip_cycles(&cpu), FETCH_CYCLES + 26 + 5 * 46);
+    }
+}
@@ -0,0 +1,390 @@
+//! Recorders that capture one frame of video or audio per 60 Hz tick: animated
+//! GIFs for sharing, and raw Y4M video plus WAV audio for external encoders.
+//! Video frames are always 128x64 pixels times the scale, lores frames being
+//! doubled, so a recording can span resolution changes.
+use crate::{cpu::Cpu, image::Palette, machine::Machine, platform::Platform, EmuError};
+use std::{
+    collections::HashMap,
+    io::{Seek, SeekFrom, Write},
+};
+
+const WIDTH: usize = 128;
+const HEIGHT: usize = 64;
+/// The WAV sample rate, giving exactly 735 samples per frame.
+const SAMPLE_RATE: u32 = 44100;
+const VOLUME: f64 = 0.5;
+
+/// Renders the display as palette indices at the fixed recording size.
+fn indices(cpu: &Cpu, scale: usize) -> Vec<u8> {
+    let (cols, _) = cpu.resolution();
+    let factor = scale * WIDTH / cols;
+    let mut out = Vec::with_capacity(WIDTH * HEIGHT * scale * scale);
+    for row in cpu.display_buffer.chunks(cols) {
+        let line: Vec<u8> = row.iter().flat_map(|&pixel| [pixel & 0x3].repeat(factor)).collect();
+        for _ in 0..factor {
+            out.extend_from_slice(&line);
+        }
+    }
+    out
+}
+
+/// Writes an animated GIF, merging runs of identical frames into one.
+pub struct GifWriter<W: Write> {
+    out: W,
+    scale: usize,
+    /// Frames captured so far
+    tick: u64,
+    /// The last distinct frame and the tick it was captured on, written once
+    /// its duration is known
+    pending: Option<(Vec<u8>, u64)>,
+}
+
+impl<W: Write> GifWriter<W> {
+    /// Writes the GIF header, looping forever and using `palette` as the global colour table.
+    pub fn new(mut out: W, scale: usize, palette: &Palette) -> Result<GifWriter<W>, EmuError> {
+        let scale = scale.max(1);
+        out.write_all(b"GIF89a")?;
+        out.write_all(&u16::try_from(WIDTH * scale)?.to_le_bytes())?;
+        out.write_all(&u16::try_from(HEIGHT * scale)?.to_le_bytes())?;
+        out.write_all(&[0x91, 0, 0])?; // global colour table of 4 entries, 2 bits per channel
+        out.write_all(&palette.concat())?;
+        out.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+        Ok(GifWriter { out, scale, tick: 0, pending: None })
+    }
+    pub fn frame(&mut self, cpu: &Cpu) -> Result<(), EmuError> {
+        let frame = indices(cpu, self.scale);
+        if self.pending.as_ref().is_none_or(|(pending, _)| *pending != frame) {
+            self.flush()?;
+            self.pending = Some((frame, self.tick));
+        }
+        self.tick += 1;
+        Ok(())
+    }
+    /// Writes the last frame and the trailer, returning the underlying writer.
+    pub fn finish(mut self) -> Result<W, EmuError> {
+        self.flush()?;
+        self.out.write_all(&[0x3B])?;
+        Ok(self.out)
+    }
+    fn flush(&mut self) -> Result<(), EmuError> {
+        let Some((frame, start)) = self.pending.take() else {
+            return Ok(());
+        };
+        // GIF delays are in hundredths of a second, so truncate both ends of the
+        // frame to keep the total in sync with 60 Hz
+        let delay = u16::try_from(self.tick * 100 / 60 - start * 100 / 60).unwrap_or(u16::MAX);
+        self.out.write_all(&[0x21, 0xF9, 0x04, 0x00])?;
+        self.out.write_all(&delay.to_le_bytes())?;
+        self.out.write_all(&[0x00, 0x00, 0x2C, 0, 0, 0, 0])?;
+        self.out.write_all(&u16::try_from(WIDTH * self.scale)?.to_le_bytes())?;
+        self.out.write_all(&u16::try_from(HEIGHT * self.scale)?.to_le_bytes())?;
+        self.out.write_all(&[0x00, 2])?; // no local colour table, minimum code size 2
+        for block in lzw(2, &frame).chunks(255) {
+            self.out.write_all(&[u8::try_from(block.len())?])?;
+            self.out.write_all(block)?;
+        }
+        self.out.write_all(&[0x00])?;
+        Ok(())
+    }
+}
+
+/// Compresses `data` with GIF's variable width LZW.
+fn lzw(min_size: u32, data: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << min_size;
+    let end = clear + 1;
+    let mut out = Vec::new();
+    let (mut bits, mut len) = (0u32, 0u32);
+    let mut emit = |code: u16, size: u32, out: &mut Vec<u8>| {
+        bits |= u32::from(code) << len;
+        len += size;
+        while len >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            len -= 8;
+        }
+    };
+
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = min_size + 1;
+    let mut next = end + 1;
+    emit(clear, size, &mut out);
+    let Some((&first, rest)) = data.split_first() else {
+        emit(end, size, &mut out);
+        if len > 0 {
+            out.push(bits as u8);
+        }
+        return out;
+    };
+    let mut prefix = u16::from(first);
+    for &byte in rest {
+        if let Some(&code) = table.get(&(prefix, byte)) {
+            prefix = code;
+            continue;
+        }
+        emit(prefix, size, &mut out);
+        // Widen codes only after writing the first code that could be the new
+        // table entry, as decoders lag one code behind when building the table
+        if next == 1 << size && size < 12 {
+            size += 1;
+        }
+        if next < 0x1000 {
+            table.insert((prefix, byte), next);
+            next += 1;
+        } else {
+            emit(clear, size, &mut out);
+            table.clear();
+            size = min_size + 1;
+            next = end + 1;
+        }
+        prefix = u16::from(byte);
+    }
+    emit(prefix, size, &mut out);
+    if next == 1 << size && size < 12 {
+        size += 1;
+    }
+    emit(end, size, &mut out);
+    if len > 0 {
+        out.push(bits as u8);
+    }
+    out
+}
+
+/// Writes uncompressed YUV4MPEG2 video at 60 frames per second.
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    scale: usize,
+    /// The Y, U and V value of each palette entry
+    yuv: [[u8; 3]; 4],
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(mut out: W, scale: usize, palette: &Palette) -> Result<Y4mWriter<W>, EmuError> {
+        let scale = scale.max(1);
+        writeln!(out, "YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444", WIDTH * scale, HEIGHT * scale)?;
+        let yuv = palette.map(|[r, g, b]| {
+            let (r, g, b) = (f64::from(r), f64::from(g), f64::from(b));
+            // Full range BT.601
+            let y = 0.299 * r + 0.587 * g + 0.114 * b;
+            [y, 128.0 + (b - y) * 0.564, 128.0 + (r - y) * 0.713].map(|c| c.round().clamp(0.0, 255.0) as u8)
+        });
+        Ok(Y4mWriter { out, scale, yuv })
+    }
+    pub fn frame(&mut self, cpu: &Cpu) -> Result<(), EmuError> {
+        let frame = indices(cpu, self.scale);
+        self.out.write_all(b"FRAME\n")?;
+        for component in 0..3 {
+            let plane: Vec<u8> = frame.iter().map(|&index| self.yuv[usize::from(index)][component]).collect();
+            self.out.write_all(&plane)?;
+        }
+        Ok(())
+    }
+    pub fn finish(mut self) -> Result<W, EmuError> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Writes the beeper as 16-bit mono PCM, the XO-CHIP pattern on that platform
+/// and a 440 Hz tone otherwise.
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    samples: u32,
+    /// Position within the pattern or tone, carried between frames
+    phase: f64,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Writes a header whose sizes are filled in by `finish`.
+    pub fn new(mut out: W) -> Result<WavWriter<W>, EmuError> {
+        out.write_all(&header(0))?;
+        Ok(WavWriter { out, samples: 0, phase: 0.0 })
+    }
+    pub fn frame(&mut self, machine: &Machine) -> Result<(), EmuError> {
+        let cpu = &machine.cpu;
+        let count = SAMPLE_RATE / 60;
+        let mut pcm = Vec::with_capacity(count as usize * 2);
+        for _ in 0..count {
+            let sample = if !machine.beeper() {
+                self.phase = 0.0;
+                0.0
+            } else if machine.quirks.platform == Platform::XoChip {
+                let bit = (self.phase as usize) % (cpu.pattern.len() * 8);
+                self.phase = (self.phase + f64::from(cpu.playback_rate()) / f64::from(SAMPLE_RATE)) % 128.0;
+                if cpu.pattern[bit / 8] & 0x80 >> (bit % 8) != 0 { VOLUME } else { -VOLUME }
+            } else {
+                self.phase = (self.phase + 440.0 / f64::from(SAMPLE_RATE)) % 1.0;
+                VOLUME * (self.phase * std::f64::consts::TAU).sin()
+            };
+            pcm.extend_from_slice(&((sample * f64::from(i16::MAX)) as i16).to_le_bytes());
+        }
+        self.out.write_all(&pcm)?;
+        self.samples += count;
+        Ok(())
+    }
+    /// Fills in the header sizes, returning the underlying writer.
+    pub fn finish(mut self) -> Result<W, EmuError> {
+        self.out.seek(SeekFrom::Start(0))?;
+        self.out.write_all(&header(self.samples * 2))?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// A canonical 44 byte WAV header for `data` bytes of 16-bit mono PCM.
+fn header(data: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(44);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    out.extend_from_slice(&1u16.to_le_bytes()); // mono
+    out.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    out.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes()); // block align
+    out.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data.to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{image::GREYSCALE, Quirks};
+    use std::io::Cursor;
+
+    /// A GIF LZW decoder written from the specification, returning the pixels
+    /// and the number of clear codes seen.
+    fn unlzw(min_size: u32, data: &[u8]) -> (Vec<u8>, usize) {
+        let clear = 1usize << min_size;
+        let end = clear + 1;
+        let reset = || -> Vec<Vec<u8>> { (0..=end).map(|code| vec![code as u8]).collect() };
+        let mut table = reset();
+        let mut size = min_size + 1;
+        let (mut bit, mut clears) = (0, 0);
+        let mut prev: Option<usize> = None;
+        let mut out = Vec::new();
+        loop {
+            let code = (0..size).fold(0, |code, i| {
+                let at = bit + i as usize;
+                code | usize::from(data[at / 8] >> (at % 8) & 1) << i
+            });
+            bit += size as usize;
+            if code == clear {
+                table = reset();
+                size = min_size + 1;
+                prev = None;
+                clears += 1;
+                continue;
+            }
+            if code == end {
+                assert_eq!(data.len(), bit.div_ceil(8), "data after the end code");
+                return (out, clears);
+            }
+            let entry = match (prev, table.get(code)) {
+                (_, Some(entry)) => entry.clone(),
+                (Some(prev), None) if code == table.len() => [&table[prev][..], &table[prev][..1]].concat(),
+                _ => panic!("code {code} is not in the table of {}", table.len()),
+            };
+            if let Some(prev) = prev
+                && table.len() < 0x1000
+            {
+                table.push([&table[prev][..], &entry[..1]].concat());
+                if table.len() == 1 << size && size < 12 {
+                    size += 1;
+                }
+            }
+            out.extend_from_slice(&entry);
+            prev = Some(code);
+        }
+    }
+
+    /// The image data and delay of each frame in a GIF written by `GifWriter`.
+    fn frames(gif: &[u8]) -> Vec<(Vec<u8>, u16)> {
+        assert_eq!(gif[..6], *b"GIF89a");
+        // Header, global colour table and looping extension
+        let mut at = 13 + 12 + 19;
+        let mut frames = Vec::new();
+        while gif[at] != 0x3B {
+            assert_eq!(gif[at..at + 4], [0x21, 0xF9, 0x04, 0x00]);
+            let delay = u16::from_le_bytes([gif[at + 4], gif[at + 5]]);
+            at += 8;
+            assert_eq!(gif[at], 0x2C);
+            let min_size = u32::from(gif[at + 10]);
+            at += 11;
+            let mut data = Vec::new();
+            while gif[at] != 0 {
+                let len = usize::from(gif[at]);
+                data.extend_from_slice(&gif[at + 1..at + 1 + len]);
+                at += 1 + len;
+            }
+            at += 1;
+            let (pixels, clears) = unlzw(min_size, &data);
+            // A full table has to be cleared mid-frame for every frame here
+            assert!(clears > 1, "only {clears} clear codes");
+            frames.push((pixels, delay));
+        }
+        assert_eq!(at, gif.len() - 1);
+        frames
+    }
+
+    #[test]
+    fn gif_frames_decode_exactly() {
+        let mut machine = Machine::new(Quirks::for_platform(Platform::SchipModern), 10);
+        let mut gif = GifWriter::new(Vec::new(), 4, &GREYSCALE).unwrap();
+        let mut expected = Vec::new();
+        let mut seed = 1u32;
+        for frame in 0..6 {
+            // Noise on both planes defeats the compression, alternating resolutions
+            machine.cpu.hires = frame % 2 == 0;
+            let (cols, rows) = machine.cpu.resolution();
+            machine.cpu.display_buffer = (0..cols * rows)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    (seed >> 16) as u8 & 0x3
+                })
+                .collect();
+            expected.push(indices(&machine.cpu, 4));
+            gif.frame(&machine.cpu).unwrap();
+        }
+        let gif = gif.finish().unwrap();
+        assert_eq!(gif[6..10], [0x00, 0x02, 0x00, 0x01]);
+
+        let frames = frames(&gif);
+        assert_eq!(frames.len(), 6);
+        for ((pixels, _), expected) in frames.iter().zip(&expected) {
+            assert_eq!(pixels.len(), 512 * 256);
+            assert!(pixels == expected);
+        }
+        assert_eq!(frames.iter().map(|&(_, delay)| delay).sum::<u16>(), 6 * 100 / 60);
+    }
+
+    #[test]
+    fn lzw_round_trips_short_runs() {
+        for data in [&[][..], &[1], &[0, 0, 0, 0, 0, 0, 0, 0], &[0, 1, 2, 3, 3, 2, 1, 0, 0, 1, 2, 3]] {
+            assert_eq!(unlzw(2, &lzw(2, data)), (data.to_vec(), 1));
+        }
+    }
+
+    #[test]
+    fn wav_sizes_are_filled_in() {
+        let mut machine = Machine::new(Quirks::for_platform(Platform::Chip8), 10);
+        let mut wav = WavWriter::new(Cursor::new(Vec::new())).unwrap();
+        for frame in 0..3 {
+            machine.cpu.sound_timer = if frame == 1 { 2 } else { 0 };
+            wav.frame(&machine).unwrap();
+        }
+        let wav = wav.finish().unwrap().into_inner();
+        let data = 3 * 735 * 2;
+        assert_eq!(wav.len(), 44 + data);
+        assert_eq!(wav[..4], *b"RIFF");
+        assert_eq!(wav[4..8], (36 + data as u32).to_le_bytes());
+        assert_eq!(wav[36..40], *b"data");
+        assert_eq!(wav[40..44], (data as u32).to_le_bytes());
+        // Silent, then the tone
+        assert!(wav[44..44 + 735 * 2].iter().all(|&byte| byte == 0));
+        assert!(wav[44 + 735 * 2..44 + 735 * 4].iter().any(|&byte| byte != 0));
+    }
+}
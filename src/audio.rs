@@ -0,0 +1,71 @@
+use crate::EmuError;
+use chip8::{
+    audio::{PatternSource, Voice, SAMPLE_RATE},
+    Machine, Platform,
+};
+use rodio::{source::SineWave, OutputStream, Sink, Source};
+use std::{sync::Arc, time::Duration};
+
+/// Plays a `PatternSource` through rodio.
+struct Pattern(PatternSource);
+
+impl Iterator for Pattern {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.0.next()
+    }
+}
+
+impl Source for Pattern {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        1
+    }
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+pub struct Audio {
+    _stream: OutputStream,
+    sink: Sink,
+    voice: Arc<Voice>,
+    playing: bool,
+}
+
+impl Audio {
+    pub fn new() -> Result<Audio, EmuError> {
+        let (stream, stream_handle) = OutputStream::try_default()?;
+        let sink = Sink::try_new(&stream_handle)?;
+        Ok(Audio { _stream: stream, sink, voice: Arc::default(), playing: false })
+    }
+    /// Silences the beeper until the next `update`, e.g. while emulation is paused.
+    pub fn pause(&mut self) {
+        self.sink.stop();
+        self.playing = false;
+    }
+    pub fn update(&mut self, machine: &Machine) {
+        let xo_chip = machine.quirks.platform == Platform::XoChip;
+        if xo_chip {
+            self.voice.set(&machine.cpu.pattern, machine.cpu.playback_rate());
+        }
+        let beeper = machine.beeper();
+        if beeper && !self.playing {
+            if xo_chip {
+                self.sink.append(Pattern(PatternSource::new(Arc::clone(&self.voice))));
+            } else {
+                self.sink.append(SineWave::new(440.0));
+            }
+            self.playing = true;
+        } else if !beeper {
+            self.sink.stop();
+            self.playing = false;
+        }
+    }
+}
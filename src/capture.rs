@@ -0,0 +1,62 @@
+use crate::EmuError;
+use chip8::{
+    image::Palette,
+    video::{GifWriter, WavWriter, Y4mWriter},
+    Machine,
+};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+/// A recording of the display, and for Y4M the beeper, one frame per 60 Hz tick.
+pub enum Capture {
+    Gif(GifWriter<BufWriter<File>>),
+    /// Y4M video with a WAV of the same name next to it
+    Video(Y4mWriter<BufWriter<File>>, WavWriter<BufWriter<File>>),
+}
+
+impl Capture {
+    /// Starts a recording, choosing the format from the extension of `path`.
+    pub fn start(path: &Path, scale: usize, palette: &Palette) -> Result<Capture, EmuError> {
+        let create = |path: &Path| -> Result<BufWriter<File>, EmuError> { Ok(BufWriter::new(File::create(path)?)) };
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gif") => Ok(Capture::Gif(GifWriter::new(create(path)?, scale, palette)?)),
+            Some("y4m") => Ok(Capture::Video(
+                Y4mWriter::new(create(path)?, scale, palette)?,
+                WavWriter::new(create(&path.with_extension("wav"))?)?,
+            )),
+            _ => Err(EmuError::Capture(format!("can't record to '{}', expected a .gif or .y4m file", path.display()))),
+        }
+    }
+    pub fn frame(&mut self, machine: &Machine) -> Result<(), EmuError> {
+        match self {
+            Capture::Gif(gif) => gif.frame(&machine.cpu)?,
+            Capture::Video(y4m, wav) => {
+                y4m.frame(&machine.cpu)?;
+                wav.frame(machine)?;
+            },
+        }
+        Ok(())
+    }
+    pub fn finish(self) -> Result<(), EmuError> {
+        match self {
+            Capture::Gif(gif) => drop(gif.finish()?),
+            Capture::Video(y4m, wav) => {
+                y4m.finish()?;
+                wav.finish()?;
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Returns the first free `<rom>-N.<extension>` next to the ROM.
+pub fn next_path(rom: &Path, extension: &str) -> PathBuf {
+    let stem = rom.file_stem().unwrap_or_default().to_string_lossy();
+    (1..)
+        .map(|n| rom.with_file_name(format!("{stem}-{n}.{extension}")))
+        .find(|path| !path.exists())
+        .unwrap_or_default()
+}
@@ -0,0 +1,133 @@
+use crate::{draw, theme::Theme, EmuError};
+use chip8::{MemoryQuirk, Platform, Quirks, Timing};
+use sdl2::pixels::Color;
+use serde::{de, Deserialize, Deserializer};
+use std::{collections::HashMap, fmt::Display, fs, path::Path, str::FromStr};
+
+/// Per-ROM settings, read from a TOML file such as `game.toml` next to `game.ch8`
+/// or from a ROM database entry.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Only informational
+    pub title: Option<String>,
+    #[serde(deserialize_with = "parse")]
+    pub platform: Option<Platform>,
+    /// The instructions per frame
+    pub speed: Option<u32>,
+    /// The instructions per second, an alternative to `speed`
+    pub ips: Option<u32>,
+    /// How much runs per frame, defaulting to `vip` on that platform unless a
+    /// speed is given
+    #[serde(deserialize_with = "parse")]
+    pub timing: Option<Timing>,
+    pub quirks: QuirkOverrides,
+    /// Extra bindings from SDL scancode names to CHIP-8 keys, reloaded with F2
+    pub keys: HashMap<String, u8>,
+    /// A built-in theme, see `theme::THEMES`
+    #[serde(deserialize_with = "parse")]
+    pub theme: Option<Theme>,
+    /// Colours for pixels lit on no plane, plane 1, plane 2 and both planes, as hex
+    /// strings, replacing those of the theme
+    #[serde(deserialize_with = "parse_colors")]
+    pub colors: Option<Vec<Color>>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, EmuError> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+    /// Overlays `other` onto this config, its settings taking priority.
+    pub fn merge(&mut self, other: Config) {
+        self.title = other.title.or(self.title.take());
+        self.platform = other.platform.or(self.platform);
+        // Either form of the speed replaces both, so --speed beats a database `ips`,
+        // and also a timing that would ignore it
+        if other.speed.is_some() || other.ips.is_some() {
+            (self.speed, self.ips, self.timing) = (other.speed, other.ips, None);
+        }
+        self.timing = other.timing.or(self.timing);
+        self.quirks.merge(other.quirks);
+        self.keys.extend(other.keys);
+        self.theme = other.theme.or(self.theme);
+        self.colors = other.colors.or(self.colors.take());
+    }
+}
+
+/// Individual quirks that replace those of the selected platform.
+#[derive(clap::Args, Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuirkOverrides {
+    /// Shift VX in place instead of shifting VY into VX
+    #[arg(long = "quirk-shift", value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub shift: Option<bool>,
+    /// Jump to XNN + VX instead of NNN + V0 for BNNN
+    #[arg(long = "quirk-jump", value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub jump: Option<bool>,
+    /// Reset VF after the logic instructions 8XY1, 8XY2 and 8XY3
+    #[arg(long = "quirk-logic", value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub logic: Option<bool>,
+    /// Wrap sprites around the edges of the screen instead of clipping them
+    #[arg(long = "quirk-wrap", value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub wrap: Option<bool>,
+    /// Wait for the display interrupt before drawing, allowing one DXYN per frame
+    #[arg(long = "quirk-vblank", value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub vblank: Option<bool>,
+    /// How FX55 and FX65 change I (increment, increment-x, unchanged)
+    #[arg(long = "quirk-memory", value_name = "MODE")]
+    #[serde(deserialize_with = "parse")]
+    pub memory: Option<MemoryQuirk>,
+}
+
+impl QuirkOverrides {
+    /// Overlays `other` onto these overrides, its settings taking priority.
+    pub fn merge(&mut self, other: QuirkOverrides) {
+        self.shift = other.shift.or(self.shift);
+        self.jump = other.jump.or(self.jump);
+        self.logic = other.logic.or(self.logic);
+        self.wrap = other.wrap.or(self.wrap);
+        self.vblank = other.vblank.or(self.vblank);
+        self.memory = other.memory.or(self.memory);
+    }
+    pub fn apply(&self, quirks: &mut Quirks) {
+        if let Some(shift) = self.shift {
+            quirks.shift = shift;
+        }
+        if let Some(jump) = self.jump {
+            quirks.jump = jump;
+        }
+        if let Some(logic) = self.logic {
+            quirks.logic = logic;
+        }
+        if let Some(wrap) = self.wrap {
+            quirks.wrap = wrap;
+        }
+        if let Some(vblank) = self.vblank {
+            quirks.vblank = vblank;
+        }
+        if let Some(memory) = self.memory {
+            quirks.set_memory(memory);
+        }
+    }
+}
+
+/// Deserializes an optional string through `FromStr`, for the core types which don't depend on serde.
+fn parse<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| s.parse().map_err(de::Error::custom))
+        .transpose()
+}
+
+fn parse_colors<'de, D>(deserializer: D) -> Result<Option<Vec<Color>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<Vec<String>>::deserialize(deserializer)?
+        .map(|colors| colors.iter().map(|s| draw::parse_color(s).map_err(de::Error::custom)).collect())
+        .transpose()
+}
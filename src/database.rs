@@ -0,0 +1,50 @@
+//! A database of per-ROM settings keyed by the SHA-1 of the ROM, like the community
+//! chip-8-database, read from `--database` or `database.json` in the working
+//! directory. Entries use the same fields as a config file, e.g.
+//!
+//! ```json
+//! {
+//!     "0a1b2c...": {
+//!         "title": "Some Game",
+//!         "platform": "xo-chip",
+//!         "ips": 12000,
+//!         "quirks": { "wrap": false },
+//!         "keys": { "Up": 5, "Down": 8 },
+//!         "theme": "octo",
+//!         "colors": ["#000000", "#FFFFFF", "#AAAAAA", "#555555"]
+//!     }
+//! }
+//! ```
+use crate::{config::Config, EmuError};
+use std::{collections::HashMap, fs, path::Path};
+
+/// The database read from the working directory when `--database` isn't given.
+const LOCAL: &str = "database.json";
+
+pub fn hash(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+fn find(database: &str, hash: &str) -> Result<Option<Config>, EmuError> {
+    let entries: HashMap<String, Config> = serde_json::from_str(database)?;
+    Ok(entries.into_iter().find_map(|(key, entry)| key.eq_ignore_ascii_case(hash).then_some(entry)))
+}
+
+/// Looks up the entry for `hash` in `path`, or in `database.json` if present. An
+/// unreadable implicit database only warns, as it may be an unrelated file.
+pub fn lookup(hash: &str, path: Option<&Path>) -> Result<Option<Config>, EmuError> {
+    if let Some(path) = path {
+        return find(&fs::read_to_string(path)?, hash);
+    }
+    let path = Path::new(LOCAL);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    match fs::read_to_string(path).map_err(EmuError::from).and_then(|database| find(&database, hash)) {
+        Ok(entry) => Ok(entry),
+        Err(e) => {
+            eprintln!("Warning: ignoring {LOCAL}: {e}");
+            Ok(None)
+        },
+    }
+}
@@ -0,0 +1,183 @@
+use chip8::{disasm::{self, Syntax}, Machine};
+use std::{
+    io::{self, BufRead, Write},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+const HELP: &str = "\
+Commands (addresses in hex, other numbers decimal unless prefixed with 0x):
+  regs              show the registers
+  mem ADDR LEN      dump LEN bytes of memory from ADDR
+  stack             show the call stack
+  break [ADDR]      toggle a breakpoint at ADDR, or list breakpoints
+  step [N]          execute N instructions (default 1)
+  continue          resume execution
+  pause             pause execution
+  set REG VAL       set a register (v0-vf, i, pc, dt, st)
+  help              show this message";
+
+/// A console debugger reading commands from stdin while the emulator keeps running.
+pub struct Debugger {
+    commands: Receiver<String>,
+    pub paused: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        println!("{HELP}");
+        prompt();
+        Debugger { commands, paused: true }
+    }
+    pub fn pause(&mut self, machine: &Machine) {
+        self.paused = true;
+        print!("Paused at ");
+        print_next(machine);
+        prompt();
+    }
+    /// Runs any commands entered since the last call.
+    pub fn update(&mut self, machine: &mut Machine) {
+        loop {
+            match self.commands.try_recv() {
+                Ok(line) => {
+                    if let Err(e) = self.execute(machine, &line) {
+                        println!("{e}");
+                    }
+                    prompt();
+                },
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => return,
+            }
+        }
+    }
+    fn execute(&mut self, machine: &mut Machine, line: &str) -> Result<(), String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => (),
+            ["help" | "h"] => println!("{HELP}"),
+            ["regs" | "r"] => print_registers(machine),
+            ["mem" | "m", addr, len] => print_memory(machine, parse_address(addr)?, parse_number(len)?),
+            ["stack"] => {
+                for (depth, addr) in machine.cpu.stack.iter().enumerate().rev() {
+                    println!("{depth:2}: {addr:#05X}");
+                }
+            },
+            ["break" | "b"] => {
+                let mut breakpoints: Vec<_> = machine.breakpoints.iter().collect();
+                breakpoints.sort();
+                for addr in breakpoints {
+                    println!("{addr:#05X}");
+                }
+            },
+            ["break" | "b", addr] => {
+                let addr = u16::try_from(parse_address(addr)?).map_err(|e| e.to_string())?;
+                if machine.breakpoints.remove(&addr) {
+                    println!("Removed breakpoint at {addr:#05X}");
+                } else {
+                    machine.breakpoints.insert(addr);
+                    println!("Added breakpoint at {addr:#05X}");
+                }
+            },
+            ["step" | "s"] => step(machine, 1),
+            ["step" | "s", count] => step(machine, parse_number(count)?),
+            ["continue" | "c"] => {
+                // Run the instruction at a breakpoint rather than stopping on it again
+                machine.skip_breakpoint();
+                self.paused = false;
+            },
+            ["pause" | "p"] => self.pause(machine),
+            ["set", register, value] => set_register(machine, register, parse_number(value)?)?,
+            _ => return Err(format!("Unknown command '{line}', type 'help' for a list of commands")),
+        }
+        Ok(())
+    }
+}
+
+fn prompt() {
+    print!("> ");
+    let _ = io::stdout().flush();
+}
+
+fn parse_address(s: &str) -> Result<usize, String> {
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    usize::from_str_radix(hex, 16).map_err(|_| format!("Invalid address '{s}'"))
+}
+
+fn parse_number(s: &str) -> Result<usize, String> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("Invalid number '{s}'"))
+}
+
+fn print_registers(machine: &Machine) {
+    let cpu = &machine.cpu;
+    for (row, registers) in cpu.v.chunks(8).enumerate() {
+        let line: Vec<String> = registers
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("V{:X}={value:02X}", row * 8 + i))
+            .collect();
+        println!("{}", line.join(" "));
+    }
+    println!(
+        "PC={:04X} I={:04X} SP={} DT={:02X} ST={:02X} OP={:04X}",
+        cpu.pc, cpu.i, cpu.stack.len(), cpu.delay_timer, cpu.sound_timer, cpu.opcode,
+    );
+}
+
+fn print_memory(machine: &Machine, addr: usize, len: usize) {
+    let end = addr.saturating_add(len).min(machine.cpu.memory.len());
+    for (row, bytes) in machine.cpu.memory[addr.min(end)..end].chunks(16).enumerate() {
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+        println!("{:04X}: {}", addr + row * 16, hex.join(" "));
+    }
+}
+
+fn step(machine: &mut Machine, count: usize) {
+    for _ in 0..count {
+        if let Err(e) = machine.step() {
+            println!("Error: {e}");
+            break;
+        }
+    }
+    print_next(machine);
+}
+
+/// Prints the address and disassembly of the instruction about to be executed.
+fn print_next(machine: &Machine) {
+    let memory = &machine.cpu.memory;
+    let word = |addr: usize| u16::from_be_bytes([memory[addr & 0xFFFF], memory[(addr + 1) & 0xFFFF]]);
+    let pc = usize::from(machine.cpu.pc);
+    let opcode = word(pc);
+    let text = disasm::instruction(opcode, word(pc + 2), Syntax::Octo).unwrap_or_else(|| "(data)".to_owned());
+    println!("{pc:#05X}: {opcode:04X}  {text}");
+}
+
+fn set_register(machine: &mut Machine, register: &str, value: usize) -> Result<(), String> {
+    let cpu = &mut machine.cpu;
+    let invalid = |_| format!("Value {value} is too large for {register}");
+    match register.to_ascii_lowercase().as_str() {
+        "i" => cpu.i = u16::try_from(value).map_err(invalid)?,
+        "pc" => cpu.pc = u16::try_from(value).map_err(invalid)?,
+        "dt" => cpu.delay_timer = u8::try_from(value).map_err(invalid)?,
+        "st" => cpu.sound_timer = u8::try_from(value).map_err(invalid)?,
+        name => {
+            let index = name
+                .strip_prefix('v')
+                .and_then(|index| usize::from_str_radix(index, 16).ok())
+                .filter(|&index| index < cpu.v.len())
+                .ok_or_else(|| format!("Unknown register '{register}'"))?;
+            cpu.v[index] = u8::try_from(value).map_err(invalid)?;
+        },
+    }
+    Ok(())
+}
@@ -1,32 +1,275 @@
-use crate::{cpu::Cpu, EmuError};
+use crate::{osd::{self, Osd}, EmuError};
+use chip8::{image::Palette, Cpu};
+use std::{fmt, str::FromStr};
 use sdl2::{
-    pixels::Color, rect::Rect, render::WindowCanvas, video::Window
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{BlendMode, Texture, TextureCreator, WindowCanvas},
+    video::{FullscreenType, WindowContext},
 };
 
-pub struct Renderer {
+/// Default colours for pixels lit on no plane, plane 1, plane 2 and both planes.
+pub const PALETTE: [Color; 4] = [
+    Color::BLACK,
+    Color::WHITE,
+    Color::RGB(0xAA, 0xAA, 0xAA),
+    Color::RGB(0x55, 0x55, 0x55),
+];
+
+/// Parses a colour written as `RRGGBB`, optionally prefixed with `#`.
+pub fn parse_color(s: &str) -> Result<Color, String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    match u32::from_str_radix(hex, 16) {
+        Ok(rgb) if hex.len() == 6 => {
+            let [_, r, g, b] = rgb.to_be_bytes();
+            Ok(Color::RGB(r, g, b))
+        },
+        _ => Err(format!("invalid colour '{s}', expected a hex colour like #FF8800")),
+    }
+}
+
+/// Returns `palette` with its first entries replaced by `colors`.
+pub fn overlay(mut palette: [Color; 4], colors: &[Color]) -> [Color; 4] {
+    for (entry, color) in palette.iter_mut().zip(colors) {
+        *entry = *color;
+    }
+    palette
+}
+
+/// Converts a palette into the RGB form used by `chip8::image`.
+pub fn rgb(palette: &[Color; 4]) -> Palette {
+    palette.map(|color| [color.r, color.g, color.b])
+}
+
+/// How pixels linger after being erased, to hide the flicker of sprites
+/// that are erased and redrawn with XOR every frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Persistence {
+    /// Show the display buffer as is
+    #[default]
+    Off,
+    /// Fade erased pixels out over a number of frames, like a phosphor screen
+    Fade,
+    /// Show pixels lit in either of the last two frames
+    Blend,
+}
+
+impl FromStr for Persistence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Persistence::Off),
+            "fade" => Ok(Persistence::Fade),
+            "blend" => Ok(Persistence::Blend),
+            _ => Err(format!("unknown persistence mode '{s}', expected one of: off, fade, blend")),
+        }
+    }
+}
+
+impl fmt::Display for Persistence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Persistence::Off => "off",
+            Persistence::Fade => "fade",
+            Persistence::Blend => "blend",
+        })
+    }
+}
+
+/// Presents the display by uploading it into a streaming texture, one for each
+/// resolution, which the GPU scales to the largest size that fits the window
+/// while keeping the 2:1 aspect ratio, leaving black bars around the rest.
+pub struct Renderer<'a> {
     canvas: WindowCanvas,
+    /// Scale by any factor instead of whole multiples of the CHIP-8 pixel size
+    fractional: bool,
+    lores: Texture<'a>,
+    hires: Texture<'a>,
+    palette: [Color; 4],
+    /// RGB staging buffer for the texture upload
+    pixels: Vec<u8>,
+    persistence: Persistence,
+    /// The number of frames an erased pixel takes to fade out
+    fade_frames: u32,
+    /// For blending, the display buffer of the frame before the last
+    previous: Vec<u8>,
+    /// The display buffer of the last frame
+    current: Vec<u8>,
+    /// For fading, the brightness of each pixel and the planes it was last lit on
+    intensity: Vec<f32>,
+    lit: Vec<u8>,
+    /// Whether a frame changed the picture even if the display didn't change
+    animating: bool,
 }
 
-impl Renderer {
-    pub fn new(window: Window) -> Result<Renderer, EmuError> {
-        let canvas = window.into_canvas().build()?;
-        Ok(Renderer { canvas })
+impl<'a> Renderer<'a> {
+    pub fn new(canvas: WindowCanvas, creator: &'a TextureCreator<WindowContext>, fractional: bool) -> Result<Renderer<'a>, EmuError> {
+        let texture = |width, height| {
+            creator
+                .create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+                .map_err(|e| EmuError::Sdl(e.to_string()))
+        };
+        Ok(Renderer {
+            lores: texture(64, 32)?,
+            hires: texture(128, 64)?,
+            canvas,
+            fractional,
+            palette: PALETTE,
+            pixels: Vec::with_capacity(128 * 64 * 3),
+            persistence: Persistence::Off,
+            fade_frames: 1,
+            previous: Vec::new(),
+            current: Vec::new(),
+            intensity: Vec::new(),
+            lit: Vec::new(),
+            animating: false,
+        })
+    }
+    pub fn set_persistence(&mut self, persistence: Persistence, fade_frames: u32) {
+        self.persistence = persistence;
+        self.fade_frames = fade_frames.max(1);
+    }
+    /// Whether pixels faded or blended differently since the last draw, so the
+    /// display should be drawn even when it hasn't changed.
+    pub fn animating(&self) -> bool {
+        self.animating
     }
-    pub fn draw(&mut self, cpu: &mut Cpu) -> Result<(), EmuError> {
-        let cols = if cpu.hires { 128 } else { 64 };
-        let scale = if cpu.hires { 8 } else { 16 };
+    pub fn set_palette(&mut self, palette: [Color; 4]) {
+        self.palette = palette;
+    }
+    pub fn palette(&self) -> &[Color; 4] {
+        &self.palette
+    }
+    pub fn set_title(&mut self, title: &str) -> Result<(), EmuError> {
+        self.canvas.window_mut().set_title(title).map_err(|e| EmuError::Sdl(e.to_string()))
+    }
+    pub fn toggle_fullscreen(&mut self) -> Result<(), EmuError> {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        window.set_fullscreen(fullscreen).map_err(EmuError::Sdl)
+    }
+    /// The current size in window pixels of a CHIP-8 pixel, rounded down and at least 1.
+    pub fn scale(&self, cpu: &Cpu) -> Result<usize, EmuError> {
+        let (cols, rows) = cpu.resolution();
+        let (width, height) = self.canvas.output_size().map_err(EmuError::Sdl)?;
+        let scale = (usize::try_from(width)? / cols).min(usize::try_from(height)? / rows);
+        Ok(scale.max(1))
+    }
+    /// The centred area of the window the display is drawn to.
+    fn viewport(&self, cpu: &Cpu) -> Result<Rect, EmuError> {
+        let (cols, rows) = cpu.resolution();
+        let (width, height) = self.canvas.output_size().map_err(EmuError::Sdl)?;
+        let (w, h) = if self.fractional {
+            let scale = (f64::from(width) / cols as f64).min(f64::from(height) / rows as f64);
+            ((cols as f64 * scale) as u32, (rows as f64 * scale) as u32)
+        } else {
+            let scale = u32::try_from(self.scale(cpu)?)?;
+            (u32::try_from(cols)? * scale, u32::try_from(rows)? * scale)
+        };
+        let x = (i64::from(width) - i64::from(w)) / 2;
+        let y = (i64::from(height) - i64::from(h)) / 2;
+        Ok(Rect::new(i32::try_from(x)?, i32::try_from(y)?, w, h))
+    }
+    /// Advances fading and blending by one emulated frame, so they keep the same
+    /// pace whatever the refresh rate. Called after each frame is run.
+    pub fn frame(&mut self, cpu: &Cpu) {
+        if self.persistence == Persistence::Off {
+            return;
+        }
+        let len = cpu.display_buffer.len();
+        if self.current.len() != len {
+            // The resolution changed, so there is nothing to blend with
+            self.previous = vec![0; len];
+            self.current = vec![0; len];
+            self.intensity = vec![0.0; len];
+            self.lit = vec![0; len];
+        }
+        let step = 1.0 / self.fade_frames as f32;
+        for (i, &pixel) in cpu.display_buffer.iter().enumerate() {
+            let pixel = pixel & 0x3;
+            if pixel != 0 {
+                self.intensity[i] = 1.0;
+                self.lit[i] = pixel;
+            } else if self.intensity[i] > 0.0 {
+                self.intensity[i] = (self.intensity[i] - step).max(0.0);
+                self.animating = true;
+            }
+            self.animating |= self.previous[i] != self.current[i];
+            self.previous[i] = self.current[i];
+            self.current[i] = pixel;
+        }
+    }
+    /// Uploads the display buffer and presents it with the OSD on top, once per
+    /// refresh in which either changed.
+    pub fn draw(&mut self, cpu: &Cpu, osd: &Osd) -> Result<(), EmuError> {
+        let (cols, _) = cpu.resolution();
+        if self.current.len() != cpu.display_buffer.len() {
+            self.frame(cpu);
+        }
+        let background = self.palette[0];
+        self.animating = false;
+        self.pixels.clear();
+        for (i, &pixel) in cpu.display_buffer.iter().enumerate() {
+            let pixel = pixel & 0x3;
+            let color = match self.persistence {
+                Persistence::Off => self.palette[usize::from(pixel)],
+                Persistence::Blend => self.palette[usize::from(pixel | self.previous[i])],
+                Persistence::Fade if pixel != 0 => self.palette[usize::from(pixel)],
+                Persistence::Fade => blend(background, self.palette[usize::from(self.lit[i])], self.intensity[i]),
+            };
+            self.pixels.extend([color.r, color.g, color.b]);
+        }
+        let viewport = self.viewport(cpu)?;
+        let texture = if cpu.hires { &mut self.hires } else { &mut self.lores };
+        texture.update(None, &self.pixels, cols * 3).map_err(|e| EmuError::Sdl(e.to_string()))?;
         self.canvas.set_draw_color(Color::BLACK);
         self.canvas.clear();
-        self.canvas.set_draw_color(Color::WHITE);
-        for (i, pixel) in cpu.display_buffer.iter().enumerate() {
-            if *pixel {
-                let coord = i32::try_from(i)?;
-                let x = (coord % cols) * scale;
-                let y = (coord / cols) * scale;
-                self.canvas.fill_rect(Rect::new(x, y, u32::try_from(scale)?, u32::try_from(scale)?)).map_err(EmuError::Sdl)?;
+        self.canvas.copy(texture, None, viewport).map_err(EmuError::Sdl)?;
+        self.draw_osd(osd)?;
+        self.canvas.present();
+        Ok(())
+    }
+    /// Draws the OSD text in the foreground colour over a translucent background,
+    /// sized to stay readable as the window grows.
+    fn draw_osd(&mut self, osd: &Osd) -> Result<(), EmuError> {
+        let (top, message) = osd.text();
+        let (_, height) = self.canvas.output_size().map_err(EmuError::Sdl)?;
+        // Glyphs are 4x5 pixels of `size` with a pixel of spacing around them
+        let size = (height / 160).max(1);
+        let line_height = 7 * size;
+        let mut lines: Vec<(&str, u32)> = top.into_iter().zip((0..).map(|i| i * line_height)).collect();
+        if let Some(message) = message {
+            lines.push((message, height.saturating_sub(line_height)));
+        }
+        let background = self.palette[0];
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        for (text, y) in lines {
+            let width = (u32::try_from(text.chars().count())? * 5 + 1) * size;
+            self.canvas.set_draw_color(Color::RGBA(background.r, background.g, background.b, 0xC0));
+            self.canvas.fill_rect(Rect::new(0, i32::try_from(y)?, width, line_height)).map_err(EmuError::Sdl)?;
+            let mut pixels = Vec::new();
+            for (column, c) in (0u32..).zip(text.chars()) {
+                for (row, bits) in (0u32..).zip(osd::glyph(c)) {
+                    for bit in (0..4).filter(|bit| bits & 0x80 >> bit != 0) {
+                        let x = (column * 5 + bit + 1) * size;
+                        pixels.push(Rect::new(i32::try_from(x)?, i32::try_from(y + (row + 1) * size)?, size, size));
+                    }
+                }
             }
+            self.canvas.set_draw_color(self.palette[1]);
+            self.canvas.fill_rects(&pixels).map_err(EmuError::Sdl)?;
         }
-        self.canvas.present();
+        self.canvas.set_blend_mode(BlendMode::None);
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Mixes `from` towards `to` by `amount` between 0 and 1.
+fn blend(from: Color, to: Color, amount: f32) -> Color {
+    let mix = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * amount).round() as u8;
+    Color::RGB(mix(from.r, to.r), mix(from.g, to.g), mix(from.b, to.b))
+}
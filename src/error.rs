@@ -16,10 +16,18 @@ pub enum EmuError {
     Play(#[from] rodio::PlayError),
     #[error("Io Error: {0}")]
     Io(#[from] std::io::Error),
-    #[error("Stack Error: {0}")]
-    Stack(String),
-    #[error("Program exited")]
-    Exit(),
-    #[error("Invalid instruction {0}")]
-    Invalid(u16),
+    #[error("Failed to parse config {0}")]
+    Config(#[from] toml::de::Error),
+    #[error("Failed to parse database {0}")]
+    Database(#[from] serde_json::Error),
+    #[error("Invalid palette: {0}")]
+    Palette(String),
+    #[error("Invalid key binding: {0}")]
+    KeyBinding(String),
+    #[error("Failed to record: {0}")]
+    Capture(String),
+    #[error("VIP interpreter image must be 512 bytes, got {0}")]
+    Interpreter(usize),
+    #[error(transparent)]
+    Core(#[from] chip8::EmuError),
 }
@@ -0,0 +1,66 @@
+use crate::EmuError;
+use chip8::{image::{self, Palette}, movie::Movie, Machine};
+use std::{fs, str::FromStr};
+
+/// A scripted key press: hold `key` for `hold` frames starting at `frame`.
+#[derive(Clone, Debug)]
+pub struct KeyPress {
+    frame: u32,
+    key: usize,
+    hold: u32,
+}
+
+impl FromStr for KeyPress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("invalid key press '{s}', expected FRAME:KEY[:FRAMES] (e.g. 30:5:4)");
+        let mut parts = s.split(':');
+        let frame = parts.next().and_then(|p| p.parse().ok()).ok_or_else(usage)?;
+        let key = parts.next()
+            .and_then(|p| usize::from_str_radix(p, 16).ok())
+            .filter(|&key| key < 0x10)
+            .ok_or_else(usage)?;
+        let hold = match parts.next() {
+            Some(p) => p.parse().map_err(|_| usage())?,
+            None => 1,
+        };
+        if parts.next().is_some() {
+            return Err(usage());
+        }
+        Ok(KeyPress { frame, key, hold })
+    }
+}
+
+impl KeyPress {
+    fn held_at(&self, frame: u32) -> bool {
+        frame >= self.frame && frame - self.frame < self.hold
+    }
+}
+
+/// Runs the machine for `frames` 60 Hz frames, or the length of `movie` when replaying
+/// one, without opening a window or audio device, then prints the display as ASCII
+/// art or writes it to `png` in `palette` at `scale`.
+pub fn run(machine: &mut Machine, frames: u32, presses: &[KeyPress], movie: Option<&Movie>, png: Option<&str>, palette: &Palette, scale: usize) -> Result<(), EmuError> {
+    let frames = movie.map_or(Ok(frames), |movie| u32::try_from(movie.len()))?;
+    for frame in 0..frames {
+        if let Some(movie) = movie {
+            movie.play(frame as usize, machine);
+        } else {
+            for key in 0..0x10 {
+                let held = presses.iter().any(|press| press.key == key && press.held_at(frame));
+                machine.set_key(key, held);
+            }
+        }
+        match machine.run_frame() {
+            Err(chip8::EmuError::Exit()) => break,
+            Err(e) => eprintln!("Error: {e}"),
+            _ => (),
+        }
+    }
+    match png {
+        Some(path) => fs::write(path, image::screenshot(&machine.cpu, scale, palette)?)?,
+        None => print!("{}", image::ascii(&machine.cpu)),
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use crate::EmuError;
+use sdl2::keyboard::Scancode;
+use std::collections::HashMap;
+
+/// Maps keyboard keys to the CHIP-8 keypad by their physical position, so the
+/// keypad sits in the same place on QWERTY, AZERTY and Dvorak keyboards.
+pub struct KeyMap {
+    keys: HashMap<Scancode, usize>,
+}
+
+impl Default for KeyMap {
+    /// The usual layout, the 4x4 block below and including the 1 key on a US
+    /// keyboard standing in for the COSMAC VIP keypad.
+    fn default() -> Self {
+        let keys = [
+            (Scancode::Num1, 0x1),
+            (Scancode::Num2, 0x2),
+            (Scancode::Num3, 0x3),
+            (Scancode::Num4, 0xC),
+            (Scancode::Q, 0x4),
+            (Scancode::W, 0x5),
+            (Scancode::E, 0x6),
+            (Scancode::R, 0xD),
+            (Scancode::A, 0x7),
+            (Scancode::S, 0x8),
+            (Scancode::D, 0x9),
+            (Scancode::F, 0xE),
+            (Scancode::Z, 0xA),
+            (Scancode::X, 0x0),
+            (Scancode::C, 0xB),
+            (Scancode::V, 0xF),
+        ];
+        KeyMap { keys: keys.into_iter().collect() }
+    }
+}
+
+impl KeyMap {
+    pub fn get(&self, key: Scancode) -> Option<usize> {
+        self.keys.get(&key).copied()
+    }
+    /// Adds bindings from SDL scancode names (e.g. "Up", "Space", or "Q" for the
+    /// key where Q is on a US keyboard) to CHIP-8 keys.
+    pub fn bind(&mut self, bindings: &HashMap<String, u8>) -> Result<(), EmuError> {
+        for (name, &key) in bindings {
+            let scancode = Scancode::from_name(name).ok_or_else(|| EmuError::KeyBinding(format!("unknown key '{name}'")))?;
+            if key > 0xF {
+                return Err(EmuError::KeyBinding(format!("'{name}' is bound to {key:#X}, which is not a CHIP-8 key")));
+            }
+            self.keys.insert(scancode, usize::from(key));
+        }
+        Ok(())
+    }
+}
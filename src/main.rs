@@ -1,402 +1,618 @@
-#![allow(clippy::struct_excessive_bools)]
-mod error;
-mod quirk;
+mod audio;
+mod capture;
+mod config;
+mod database;
+mod debugger;
 mod draw;
-mod cpu;
+mod error;
+mod headless;
+mod input;
+mod osd;
+mod savestate;
+mod theme;
+mod timing;
 
 use crate::{
+    audio::Audio,
+    capture::Capture,
+    config::{Config, QuirkOverrides},
+    debugger::Debugger,
     error::EmuError,
-    quirk::Quirks,
-    cpu::Cpu,
+    headless::KeyPress,
+    input::KeyMap,
+    osd::Osd,
+    savestate::SaveSlots,
+    theme::{Theme, THEMES},
+    timing::{FpsCounter, Pacer, Ticker, TIMER_RATE},
 };
 use std::{
-    fs::File, 
-    io::{Read, Write, BufWriter},
-    time::Duration,
+    fs::{self, File},
+    io::{Read, Write, BufWriter, BufReader},
+    path::{Path, PathBuf},
 };
-use draw::Renderer;
+use chip8::{asm, disasm::{self, Syntax}, image::{self, Palette}, movie::Movie, rewind::Rewind, Generator, Machine, Platform, Quirks, Timing};
+use draw::{Persistence, Renderer};
 use sdl2::{
-    event::Event, 
+    event::{Event, WindowEvent},
+    keyboard::{Keycode, Mod},
+    pixels::Color,
+    render::WindowCanvas,
     EventPump,
 };
-use rodio::{OutputStream, Sink};
-use frand::Rand;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-const FONT: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
+const DEFAULT_SPEED: u32 = 11;
+/// Frames of history kept for rewinding, 40 seconds at 60 hz
+const REWIND_FRAMES: usize = 40 * 60;
 
-const BIGFONT: [u8; 160] = [
-    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
-    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
-    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
-    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
-    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
-    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
-    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
-    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
-    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
-    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
-    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
-    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
-    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
-    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
-    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
-    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
-];
+/// Hotkeys listed after the options in `--help`, as in the README.
+const HOTKEYS: &str = "\
+Hotkeys:
+  F1, Pause         Pause or resume
+  F2                Reload key bindings
+  F3, Shift+F3      Soft reset, hard reset
+  F4                Advance one frame while paused
+  F5, F7            Save state, load state
+  F6, Shift+F6      Select the next, previous save slot
+  F8                Cycle colour themes
+  F9                Start or stop capturing a GIF
+  F10               Show or hide the on-screen display
+  F11               Toggle fullscreen
+  F12               Save a screenshot
+  Tab (hold)        Fast-forward
+  ` (hold)          Slow motion
+  Backspace (hold)  Rewind";
 
 /// CHIP-8 Interpreter
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_help = HOTKEYS, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    args: Option<Args>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Disassemble a ROM
+    Disasm {
+        /// The path to the ROM
+        rom: PathBuf,
+        /// The assembly syntax to produce (octo, classic)
+        #[arg(long, default_value_t)]
+        syntax: Syntax,
+        /// Write the disassembly to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Assemble Octo or classic style source into a ROM
+    Asm {
+        /// The path to the source
+        source: PathBuf,
+        /// The path to write the ROM to, defaults to the source path with a .ch8 extension
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
     /// The path to the ROM
     rom: String,
-    /// The instructions per frame
-    #[arg(short, long, default_value_t = 11)]
-    speed: u32,
-    /// The refresh rate of the program in hz
+    /// The instructions per frame [default: 11]
+    #[arg(short, long)]
+    speed: Option<u32>,
+    /// The instructions per second, an alternative to --speed
+    #[arg(long, conflicts_with = "speed")]
+    ips: Option<u32>,
+    /// Run a fixed number of instructions per frame, or charge each its COSMAC VIP
+    /// cycle cost (instructions, vip) [default: vip on that platform unless a speed is given]
+    #[arg(long, conflicts_with_all = ["speed", "ips"])]
+    timing: Option<Timing>,
+    /// The platform whose quirks to emulate (chip8, schip-legacy, schip-modern, xo-chip, vip)
+    #[arg(short, long)]
+    platform: Option<Platform>,
+    #[command(flatten)]
+    quirks: QuirkOverrides,
+    /// Seed the random number generator used by CXNN, making runs repeatable
+    #[arg(long)]
+    seed: Option<u64>,
+    /// The random number generator used by CXNN (frand, vip)
+    #[arg(long, default_value_t)]
+    rng: Generator,
+    /// A 512 byte dump of the COSMAC VIP CHIP-8 interpreter, whose table `--rng vip`
+    /// reads to give the same numbers as the hardware
+    #[arg(long, value_name = "FILE")]
+    vip_interpreter: Option<PathBuf>,
+    /// A TOML file with per-ROM settings, defaults to the ROM path with a .toml extension
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// A JSON database of per-ROM settings keyed by SHA-1 [default: database.json if present]
+    #[arg(long)]
+    database: Option<PathBuf>,
+    /// How often the window is redrawn and input is read in hz, independent of the 60 hz timers
     #[arg(short, long, default_value_t = 60)]
     refresh_rate: u32,
+    /// Start paused with a debugger console on stdin
+    #[arg(long, conflicts_with = "headless")]
+    debug: bool,
+    /// Run without a window or audio device and dump the display when done, ignoring rpl.txt
+    #[arg(long)]
+    headless: bool,
+    /// The number of 60 hz frames to run in headless mode
+    #[arg(long, default_value_t = 600, requires = "headless")]
+    frames: u32,
+    /// Hold a key in headless mode, as FRAME:KEY[:FRAMES] with KEY in hex (repeatable)
+    #[arg(long, value_name = "FRAME:KEY[:FRAMES]", requires = "headless")]
+    press: Vec<KeyPress>,
+    /// Write the final display to a PNG instead of printing it in headless mode
+    #[arg(long, value_name = "PATH", requires = "headless")]
+    png: Option<String>,
+    /// A built-in colour theme (classic, octo, lcd, amber, high-contrast, colorblind), cycled with F8
+    #[arg(long)]
+    theme: Option<Theme>,
+    /// A file of up to four hex colours, one per line, for pixels lit on no plane, plane 1, plane 2 and both
+    #[arg(long, value_name = "PATH")]
+    palette: Option<PathBuf>,
+    /// The colour of pixels lit on plane 1, as hex
+    #[arg(long, value_name = "RRGGBB", value_parser = draw::parse_color)]
+    fg: Option<Color>,
+    /// The background colour, as hex
+    #[arg(long, value_name = "RRGGBB", value_parser = draw::parse_color)]
+    bg: Option<Color>,
+    /// The number of frames run per 60 hz tick while holding Tab
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    fast_forward: u32,
+    /// The number of 60 hz ticks per frame while holding the backquote key
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    slow_motion: u32,
+    /// Print the achieved frame and refresh rates every second, as also shown in the window title
+    #[arg(long, conflicts_with = "headless")]
+    fps: bool,
+    /// Start with the on-screen display of speed, state and messages shown, toggled with F10
+    #[arg(long, conflicts_with = "headless")]
+    osd: bool,
+    /// Keep erased pixels on screen to reduce flicker (off, fade, blend)
+    #[arg(long, default_value_t)]
+    persistence: Persistence,
+    /// The number of frames an erased pixel takes to fade out with --persistence fade
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    fade_frames: u32,
+    /// The initial size of a lores pixel in window pixels
+    #[arg(long, value_name = "N", default_value_t = 16)]
+    scale: u32,
+    /// Scale the display to fill the window instead of by whole multiples
+    #[arg(long)]
+    fractional: bool,
+    /// Start in fullscreen, toggled with F11
+    #[arg(long)]
+    fullscreen: bool,
+    /// The size of a CHIP-8 pixel in screenshots taken with F12 or --png [default: the window scale, or 1 in headless mode]
+    #[arg(long, value_name = "N")]
+    screenshot_scale: Option<usize>,
+    /// Record gameplay to an animated .gif, or to a .y4m video with a .wav of the beeper next to it (toggle with F9)
+    #[arg(long, value_name = "PATH", conflicts_with = "headless")]
+    capture: Option<PathBuf>,
+    /// The size of a CHIP-8 hires pixel in recordings, lores pixels being twice as large
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    capture_scale: usize,
+    /// Record the key presses of this run to a movie file, written on exit
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["headless", "replay"])]
+    record: Option<PathBuf>,
+    /// Replay a movie file recorded with --record, then hand control back to the keyboard
+    #[arg(long, value_name = "PATH")]
+    replay: Option<PathBuf>,
 }
 
-fn init() -> Result<(Renderer, Rand, EventPump), EmuError> {
+fn init(scale: u32, fullscreen: bool) -> Result<(WindowCanvas, EventPump), EmuError> {
     let sdl_context = sdl2::init().map_err(EmuError::Sdl)?;
     let video_subsystem = sdl_context.video().map_err(EmuError::Sdl)?;
 
-    let window = video_subsystem
-        .window("CHIP-8 Emulator", 1024, 512)
-        .position_centered()
-        .build()?;
+    let mut builder = video_subsystem.window("CHIP-8 Emulator", 64 * scale.max(1), 32 * scale.max(1));
+    builder.position_centered().resizable();
+    if fullscreen {
+        builder.fullscreen_desktop();
+    }
+    let window = builder.build()?;
 
-    let renderer = Renderer::new(window)?;
-    let rng = Rand::new();
+    let canvas = window.into_canvas().build()?;
     let event_pump = sdl_context.event_pump().map_err(EmuError::Sdl)?;
-    Ok((renderer, rng, event_pump))
+    Ok((canvas, event_pump))
+}
+
+fn load_flags() -> Result<[u8; 0x10], EmuError> {
+    let mut flag = [0; 0x10];
+    if let Ok(f) = File::open("rpl.txt") {
+        let reader = BufReader::new(f);
+        for (i, byte) in reader.bytes().take(flag.len()).enumerate() {
+            flag[i] = byte?;
+        }
+    }
+    Ok(flag)
+}
+
+/// Gathers the settings for `rom` from the ROM database, then the config file,
+/// then the command line, each taking priority over the last.
+fn load_config(args: &Args, rom: &[u8]) -> Result<Config, EmuError> {
+    let mut config = database::lookup(&database::hash(rom), args.database.as_deref())?.unwrap_or_default();
+    config.merge(match &args.config {
+        Some(path) => Config::load(path)?,
+        None => {
+            let path = Path::new(&args.rom).with_extension("toml");
+            if path.is_file() { Config::load(&path)? } else { Config::default() }
+        },
+    });
+    config.merge(Config {
+        platform: args.platform,
+        speed: args.speed,
+        ips: args.ips,
+        timing: args.timing,
+        quirks: args.quirks.clone(),
+        theme: args.theme,
+        ..Config::default()
+    });
+    Ok(config)
+}
+
+/// Builds the key map from the default layout and the bindings in `config`.
+fn load_keymap(config: &Config) -> Result<KeyMap, EmuError> {
+    let mut keymap = KeyMap::default();
+    keymap.bind(&config.keys)?;
+    Ok(keymap)
+}
+
+/// Builds the palette from the theme, then the config colours, the palette
+/// file and the --fg and --bg options, each taking priority over the last.
+fn load_palette(args: &Args, config: &Config) -> Result<[Color; 4], EmuError> {
+    let mut palette = config.theme.map_or(draw::PALETTE, |theme| theme.palette);
+    palette = draw::overlay(palette, config.colors.as_deref().unwrap_or_default());
+    if let Some(path) = &args.palette {
+        palette = draw::overlay(palette, &theme::load(path)?);
+    }
+    palette[0] = args.bg.unwrap_or(palette[0]);
+    palette[1] = args.fg.unwrap_or(palette[1]);
+    Ok(palette)
+}
+
+fn save_movie(movie: Option<&Movie>, path: Option<&Path>) -> Result<(), EmuError> {
+    if let (Some(movie), Some(path)) = (movie, path) {
+        fs::write(path, movie.save())?;
+    }
+    Ok(())
+}
+
+/// Writes the display to the first free `<rom>-N.png` next to the ROM.
+fn screenshot(machine: &Machine, rom: &Path, scale: usize, palette: &Palette) -> Result<PathBuf, EmuError> {
+    let path = capture::next_path(rom, "png");
+    fs::write(&path, image::screenshot(&machine.cpu, scale, palette)?)?;
+    Ok(path)
+}
+
+/// Stops any recording, or starts one to the first free `<rom>-N.gif` next to the
+/// ROM, returning a message saying which.
+fn toggle_capture(capture: &mut Option<Capture>, rom: &Path, scale: usize, palette: &Palette) -> Result<String, EmuError> {
+    match capture.take() {
+        Some(recording) => {
+            recording.finish()?;
+            Ok("Stopped recording".to_owned())
+        },
+        None => {
+            let path = capture::next_path(rom, "gif");
+            *capture = Some(Capture::start(&path, scale, palette)?);
+            Ok(format!("Recording to {}", path.display()))
+        },
+    }
 }
 
 fn main() -> Result<(), EmuError> {
-    let args = Args::parse();
-    let (mut renderer, mut rng, mut event_pump) = init()?;
-    let mut cpu = Cpu::new()?;
-    let quirks = Quirks::new();
-    let (_stream, stream_handle) = OutputStream::try_default()?;
-    let sink = Sink::try_new(&stream_handle)?;
+    let cli = Cli::parse();
+    match (cli.command, cli.args) {
+        (Some(command), _) => run_command(command),
+        (None, Some(args)) => run(&args),
+        (None, None) => unreachable!("clap requires a ROM when no subcommand is given"),
+    }
+}
+
+fn run_command(command: Command) -> Result<(), EmuError> {
+    match command {
+        Command::Disasm { rom, syntax, output } => {
+            let text = disasm::disassemble(&fs::read(rom)?, syntax);
+            match output {
+                Some(path) => fs::write(path, text)?,
+                None => print!("{text}"),
+            }
+        },
+        Command::Asm { source, output } => {
+            let rom = asm::assemble(&fs::read_to_string(&source)?)?;
+            fs::write(output.unwrap_or_else(|| source.with_extension("ch8")), rom)?;
+        },
+    }
+    Ok(())
+}
 
-    File::open(args.rom)?.read_to_end(&mut cpu.rom)?;
+fn run(args: &Args) -> Result<(), EmuError> {
+    let mut rom = Vec::new();
+    File::open(&args.rom)?.read_to_end(&mut rom)?;
+    let config = load_config(args, &rom)?;
 
-    for (i, byte) in cpu.rom.iter().enumerate() {
-        cpu.memory[i + 0x200] = *byte;
+    let platform = config.platform.unwrap_or_default();
+    let mut quirks = Quirks::for_platform(platform);
+    config.quirks.apply(&mut quirks);
+    let mut machine = Machine::new(quirks, config.speed.unwrap_or(DEFAULT_SPEED));
+    machine.ips = config.ips;
+    machine.timing = config.timing.unwrap_or(if platform == Platform::Vip && config.speed.is_none() && config.ips.is_none() {
+        Timing::Vip
+    } else {
+        Timing::Instructions
+    });
+    machine.set_rng(args.rng, args.seed.unwrap_or(machine.seed()));
+    if let Some(path) = &args.vip_interpreter {
+        let image = fs::read(path)?;
+        let page = image.get(0x100..).and_then(|page| page.try_into().ok()).ok_or(EmuError::Interpreter(image.len()))?;
+        machine.set_interpreter(page);
     }
-    for (i, char) in FONT.iter().enumerate() {
-        cpu.memory[i] = *char;
+    machine.load_rom(&rom)?;
+    // Headless runs start with clear flags so a stray rpl.txt can't change their results
+    if !args.headless {
+        machine.cpu.flag = load_flags()?;
     }
-    for (i, char) in BIGFONT.iter().enumerate() {
-        cpu.memory[i + 0x50] = *char;
+    let hash = database::hash(&rom);
+    let replay = match &args.replay {
+        Some(path) => Some(Movie::load(&fs::read(path)?)?),
+        None => None,
+    };
+    if let Some(movie) = &replay {
+        movie.apply(&hash, &mut machine)?;
     }
 
+    if args.headless {
+        let palette = draw::rgb(&load_palette(args, &config)?);
+        let scale = args.screenshot_scale.unwrap_or(1);
+        return headless::run(&mut machine, args.frames, &args.press, replay.as_ref(), args.png.as_deref(), &palette, scale);
+    }
+
+    let (canvas, mut event_pump) = init(args.scale, args.fullscreen)?;
+    let texture_creator = canvas.texture_creator();
+    let mut renderer = Renderer::new(canvas, &texture_creator, args.fractional)?;
+    renderer.set_persistence(args.persistence, args.fade_frames);
+    let mut audio = Audio::new()?;
+    let mut keymap = load_keymap(&config)?;
+    let palette = load_palette(args, &config)?;
+    renderer.set_palette(palette);
+    // F8 cycles from the starting palette through the built-in themes
+    let mut themes = 0;
+    let mut debugger = args.debug.then(Debugger::new);
+    let mut slots = SaveSlots::new(&args.rom);
+    let mut rewind = Rewind::new(REWIND_FRAMES);
+    let mut rewinding = false;
+    let mut paused = false;
+    let mut advance = false;
+    let mut fast_forward = false;
+    let mut slow_motion = false;
+    let mut slow_ticks = 0;
+    let mut ticker = Ticker::new(TIMER_RATE, args.refresh_rate);
+    let mut pacer = Pacer::new(args.refresh_rate);
+    let mut fps = FpsCounter::default();
+    let mut osd = Osd::new(args.osd);
+    let mut recording = args.record.is_some().then(|| Movie::new(hash, &machine));
+    let mut frame = 0;
+    let mut capture = args.capture.as_deref()
+        .map(|path| Capture::start(path, args.capture_scale, &draw::rgb(renderer.palette())))
+        .transpose()?;
+
     loop {
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => {
                     let f = File::create("rpl.txt")?;
                     let mut writer = BufWriter::new(f);
-                    writer.write_all(&cpu.flag)?;
-                    return Ok(())
-                }
-                Event::KeyDown { keycode: Some(key), .. } => {
-                    if let Some(key) = Cpu::match_key(key) {
-                        cpu.keys[key] = true;
+                    writer.write_all(&machine.cpu.flag)?;
+                    if let Some(capture) = capture {
+                        capture.finish()?;
                     }
+                    return save_movie(recording.as_ref(), args.record.as_deref());
+                }
+                Event::KeyDown { keycode: Some(Keycode::F5), repeat: false, .. } => match slots.save(&machine) {
+                    Ok(()) => osd.message(format!("State saved to slot {}", slots.slot)),
+                    Err(e) => osd.error(e),
+                },
+                Event::KeyDown { keycode: Some(Keycode::F6), keymod, repeat: false, .. } => {
+                    slots.select(keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD));
+                    osd.message(format!("Selected save slot {}", slots.slot));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F7), repeat: false, .. } if replay.is_some() || recording.is_some() => {
+                    osd.error("loading states is disabled while recording or replaying a movie");
                 },
-                Event::KeyUp { keycode: Some(key), .. } => {
-                    if let Some(key) = Cpu::match_key(key) {
-                        cpu.keys[key] = false;
+                Event::KeyDown { keycode: Some(Keycode::F7), repeat: false, .. } => match slots.load(&mut machine) {
+                    Ok(()) => osd.message(format!("State loaded from slot {}", slots.slot)),
+                    Err(e) => osd.error(e),
+                },
+                Event::KeyDown { keycode: Some(Keycode::F12), repeat: false, .. } => {
+                    let scale = match args.screenshot_scale {
+                        Some(scale) => scale,
+                        None => renderer.scale(&machine.cpu)?,
+                    };
+                    match screenshot(&machine, Path::new(&args.rom), scale, &draw::rgb(renderer.palette())) {
+                        Ok(path) => osd.message(format!("Saved screenshot to {}", path.display())),
+                        Err(e) => osd.error(e),
                     }
                 },
-                _ => (),
-            }
-        }
-        cpu.tick_timers(&sink);
-        for _ in 0..args.speed {
-            fetch(&mut cpu);
-            match decode(&mut cpu, &quirks, &mut rng, &mut renderer) {
-                Err(EmuError::Exit()) => return Err(EmuError::Exit()),
-                Err(e) => eprintln!("Error: {e}"),
-                _ => (),
-            }
-        }
-        if args.refresh_rate > 0 {
-            std::thread::sleep(Duration::new(0, 1_000_000_000u32 / args.refresh_rate));
-        }
-    }
-}
-
-fn fetch(cpu: &mut Cpu) {
-    cpu.opcode = u16::from_be_bytes([cpu.memory[cpu.pc as usize], cpu.memory[cpu.pc as usize + 1]]);
-    cpu.pc += 2;
-}
-
-fn decode(cpu: &mut Cpu, quirks: &Quirks, rng: &mut Rand, renderer: &mut Renderer) -> Result<(), EmuError> {
-    let x = ((cpu.opcode & 0x0F00) >> 8) as usize;
-    let y = ((cpu.opcode & 0x00F0) >> 4) as usize;
-    match (cpu.opcode & 0xF000) >> 12 {
-        0x0 => {
-            match cpu.opcode & 0x00FF {
-                0xE0 => {
-                    cpu.display_buffer.fill(false);
-                    renderer.draw(cpu)?;
+                Event::KeyDown { keycode: Some(Keycode::F10), repeat: false, .. } => osd.toggle(),
+                Event::KeyDown { keycode: Some(Keycode::F11), repeat: false, .. } => {
+                    renderer.toggle_fullscreen()?;
+                    machine.cpu.dirty = true;
                 },
-                0xEE => cpu.pc = cpu.stack.pop().ok_or(EmuError::Stack("Tried to pop from stack but stack is empty".to_owned()))?,
-                _ if y == 0xC => {
-                    let cols = if cpu.hires { 128 } else { 64 };
-                    let (pixels, len) = cpu.get_on_pixels();
-                    for i in pixels {
-                        cpu.display_buffer[(i + cols * (cpu.opcode & 0x000F) as usize) % len] = true;
+                Event::Window { win_event: WindowEvent::SizeChanged(..) | WindowEvent::Exposed, .. } => machine.cpu.dirty = true,
+                Event::KeyDown { keycode: Some(Keycode::F8), repeat: false, .. } => {
+                    themes = (themes + 1) % (THEMES.len() + 1);
+                    match themes.checked_sub(1).map(|i| THEMES[i]) {
+                        Some(theme) => {
+                            renderer.set_palette(theme.palette);
+                            osd.message(format!("Theme: {theme}"));
+                        },
+                        None => {
+                            renderer.set_palette(palette);
+                            osd.message("Theme: starting palette");
+                        },
                     }
+                    machine.cpu.dirty = true;
                 },
-                0xFB => {
-                    let (pixels, len) = cpu.get_on_pixels();
-                    for i in pixels {
-                        cpu.display_buffer[(i + 4) % len] = true;
+                Event::KeyDown { keycode: Some(Keycode::F9), repeat: false, .. } => {
+                    match toggle_capture(&mut capture, Path::new(&args.rom), args.capture_scale, &draw::rgb(renderer.palette())) {
+                        Ok(message) => osd.message(message),
+                        Err(e) => osd.error(e),
                     }
                 },
-                0xFC => {
-                    let (pixels, len) = cpu.get_on_pixels();
-                    for i in pixels {
-                        cpu.display_buffer[(i - 4) % len] = true;
+                Event::KeyDown { keycode: Some(Keycode::F1 | Keycode::PAUSE), repeat: false, .. } => {
+                    paused = !paused;
+                    osd.message(if paused { "Paused" } else { "Resumed" });
+                },
+                Event::KeyDown { keycode: Some(Keycode::F4), .. } => advance = true,
+                Event::KeyDown { keycode: Some(Keycode::F3), repeat: false, .. } if replay.is_some() || recording.is_some() => {
+                    osd.error("resetting is disabled while recording or replaying a movie");
+                },
+                Event::KeyDown { keycode: Some(Keycode::F3), keymod, repeat: false, .. } => {
+                    let hard = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    match machine.reset(hard) {
+                        Ok(()) => osd.message(if hard { "Hard reset" } else { "Soft reset" }),
+                        Err(e) => osd.error(e),
                     }
                 },
-                0xFD => {
-                    return Err(EmuError::Exit());
+                Event::KeyDown { keycode: Some(Keycode::TAB), .. } => fast_forward = true,
+                Event::KeyUp { keycode: Some(Keycode::TAB), .. } => fast_forward = false,
+                Event::KeyDown { keycode: Some(Keycode::BACKQUOTE), .. } => slow_motion = true,
+                Event::KeyUp { keycode: Some(Keycode::BACKQUOTE), .. } => slow_motion = false,
+                Event::KeyDown { keycode: Some(Keycode::BACKSPACE), .. } => rewinding = true,
+                Event::KeyUp { keycode: Some(Keycode::BACKSPACE), .. } => rewinding = false,
+                Event::KeyDown { keycode: Some(Keycode::F2), repeat: false, .. } => {
+                    match load_config(args, &rom).and_then(|config| load_keymap(&config)) {
+                        Ok(reloaded) => {
+                            keymap = reloaded;
+                            (0..0x10).for_each(|key| machine.set_key(key, false));
+                            osd.message("Reloaded key bindings");
+                        },
+                        Err(e) => osd.error(e),
+                    }
                 },
-                0xFE => {
-                    if cpu.hires {
-                        cpu.hires = false;
-                        for _ in 0x800..cpu.display_buffer.len() {
-                            cpu.display_buffer.remove(0x800);
-                        }
+                Event::KeyDown { scancode: Some(key), .. } => {
+                    if let Some(key) = keymap.get(key) {
+                        machine.set_key(key, true);
                     }
                 },
-                0xFF => {
-                    if !cpu.hires {
-                        cpu.hires = true;
-                        for _ in 0..0x1800 {
-                            cpu.display_buffer.push(false);
-                        }
+                Event::KeyUp { scancode: Some(key), .. } => {
+                    if let Some(key) = keymap.get(key) {
+                        machine.set_key(key, false);
                     }
                 },
-                _ => return Err(EmuError::Invalid(cpu.opcode)),
-            }
-        },
-        0x1 => cpu.pc = cpu.opcode & 0x0FFF,
-        0x2 => {
-            if cpu.stack.len() <= 16 {
-                cpu.stack.push(cpu.pc);
-                cpu.pc = cpu.opcode & 0x0FFF;
-            } else {
-                return Err(EmuError::Stack("Tried to push to stack but stack is at maximum length".to_owned()));
-            }
-        },
-        0x3 => cpu.skip_instruction(u16::from(cpu.v[x]) == cpu.opcode & 0x00FF),
-        0x4 => cpu.skip_instruction(u16::from(cpu.v[x]) != cpu.opcode & 0x00FF),
-        0x5 => cpu.skip_instruction(cpu.v[x] == cpu.v[y]),
-        0x6 => (cpu.v[x], _) = 0u8.overflowing_add((cpu.opcode & 0x00FF) as u8),
-        0x7 => (cpu.v[x], _) = cpu.v[x].overflowing_add((cpu.opcode & 0x00FF) as u8),
-        0x8 => decode_8(cpu, quirks, x, y)?,
-        0x9 => cpu.skip_instruction(cpu.v[x] != cpu.v[y]),
-        0xA => cpu.i = cpu.opcode & 0x0FFF,
-        0xB => {
-            let i = quirks.jump(cpu, x);
-            cpu.pc = (cpu.opcode & 0x0FFF) + u16::from(i);
-        },
-        0xC => {
-            let random = rng.r#gen::<u8>();
-            cpu.v[x] = random & (cpu.opcode & 0x00FF) as u8;
-        },
-        0xD => {
-            let cols = if cpu.hires { 128 } else { 64 };
-            let rows = if cpu.hires { 64 } else { 32 };
-            let x = u16::from(cpu.v[x]) % cols;
-            let y = u16::from(cpu.v[y]) % rows;
-            cpu.v[0xF] = 0;
-            if cpu.opcode & 0x000F != 0 {
-                draw_sprite(cpu, quirks, x, y);
-            } else {
-                draw_super_sprite(cpu, quirks, x, y);
-            }
-            renderer.draw(cpu)?;
-        },
-        0xE => {
-            match cpu.opcode & 0x00FF {
-                0x9E => cpu.skip_instruction(cpu.keys[cpu.v[x] as usize]),
-                0xA1 => cpu.skip_instruction(!cpu.keys[cpu.v[x] as usize]),
-                _ => return Err(EmuError::Invalid(cpu.opcode)),
-            }
-        },
-        0xF => decode_f(cpu, quirks, x)?,
-        _ => return Err(EmuError::Invalid(cpu.opcode)),
-    }
-    Ok(())
-}
-
-fn decode_8(cpu: &mut Cpu, quirks: &Quirks, x: usize, y: usize) -> Result<(), EmuError> {
-    match cpu.opcode & 0x000F {
-        0x0 => cpu.v[x] = cpu.v[y],
-        0x1 => {
-            cpu.v[x] |= cpu.v[y];
-            if quirks.logic {
-                cpu.v[0xF] = 0;
-            }
-        },
-        0x2 => {
-            cpu.v[x] &= cpu.v[y];
-            if quirks.logic {
-                cpu.v[0xF] = 0;
+                _ => (),
             }
-        },
-        0x3 => {
-            cpu.v[x] ^= cpu.v[y];
-            quirks.logic(cpu);
-        },
-        0x4 => {
-            let (result, carry) = cpu.v[x].overflowing_add(cpu.v[y]);
-            cpu.v[x] = result;
-            cpu.set_flag_register(carry);
-        },
-        0x5 => {
-            let (result, carry) = cpu.v[x].overflowing_sub(cpu.v[y]);
-            cpu.v[x] = result;
-            cpu.set_flag_register(!carry);
-        },
-        0x6 => {
-            quirks.shift(cpu, x, y);
-            let shifted_bit = cpu.v[x] & 1;
-            cpu.v[x] >>= 1;
-            cpu.v[0xF] = shifted_bit;
-        },
-        0x7 => {
-            let (result, carry) = cpu.v[y].overflowing_sub(cpu.v[x]);
-            cpu.v[x] = result;
-            cpu.set_flag_register(!carry);
-        },
-        0xE => {
-            quirks.shift(cpu, x, y);
-            let shifted_bit = cpu.v[x] & 0x80;
-            (cpu.v[x], _) = cpu.v[x].overflowing_shl(1);
-            cpu.v[0xF] = u8::from(shifted_bit != 0);
-        },
-        _ => return Err(EmuError::Invalid(cpu.opcode)),
-    }
-    Ok(())
-}
-
-fn decode_f(cpu: &mut Cpu, quirks: &Quirks, x: usize) -> Result<(), EmuError> {
-    match cpu.opcode & 0x00FF {
-        0x07 => cpu.v[x] = cpu.delay_timer,
-        0x15 => cpu.delay_timer = cpu.v[x],
-        0x18 => cpu.sound_timer = cpu.v[x],
-        0x1E => cpu.i += u16::from(cpu.v[x]),
-        0x0A => {
-            if let Some(key) = cpu.keys.iter().position(|&x| x) {
-                if !cpu.key_state {
-                    cpu.v[x] = u8::try_from(key)?;
-                    cpu.key_state = true;
+        }
+        if let Some(debugger) = &mut debugger {
+            debugger.update(&mut machine);
+        }
+        // The number of frames to emulate this refresh, one per 60 hz tick that came due
+        let frames = if rewinding {
+            0
+        } else if paused || debugger.as_ref().is_some_and(|debugger| debugger.paused) {
+            ticker.resync();
+            u32::from(std::mem::take(&mut advance))
+        } else if slow_motion {
+            slow_ticks += ticker.due();
+            let frames = slow_ticks / args.slow_motion.max(1);
+            slow_ticks %= args.slow_motion.max(1);
+            frames
+        } else if fast_forward {
+            ticker.due() * args.fast_forward
+        } else {
+            ticker.due()
+        };
+        if rewinding {
+            audio.pause();
+            // Step back one frame per tick, the speed the frames were played at
+            for _ in 0..ticker.due() {
+                match rewind.rewind(&mut machine) {
+                    Ok(true) => {
+                        frame -= 1;
+                        if let Some(movie) = &mut recording {
+                            movie.unrecord();
+                        }
+                        renderer.frame(&machine.cpu);
+                    },
+                    Ok(false) => break,
+                    Err(e) => {
+                        osd.error(e);
+                        break;
+                    },
                 }
-                cpu.pc -= 2;
-            } else if cpu.key_state {
-                cpu.key_state = false;
-            } else {
-                cpu.pc -= 2;
-            }
-        },
-        0x29 => cpu.i = u16::from(cpu.v[x]) * 5,
-        0x30 => cpu.i = u16::from(cpu.v[x]) * 5 + 0x50,
-        0x33 => {
-            cpu.memory[cpu.i as usize] = cpu.v[x] / 100;
-            cpu.memory[cpu.i as usize + 1] = (cpu.v[x] / 10) % 10;
-            cpu.memory[cpu.i as usize + 2] = cpu.v[x] % 10;
-        },
-        0x55 => {
-            for i in 0..=x { // x+1 cause its vX inclusive
-                cpu.memory[cpu.i as usize + i] = cpu.v[i];
             }
-            quirks.memory_increment_by_x(cpu, x)?;
-            quirks.memory_leave_i_unchanged(cpu, x)?;
-        },
-        0x65 => {
-            for i in 0..=x {
-                cpu.v[i] = cpu.memory[cpu.i as usize + i];
+        } else if frames == 0 {
+            audio.pause();
+        }
+        let instructions = machine.instructions();
+        for _ in 0..frames {
+            // A frame a breakpoint interrupted was already counted when it started
+            if !machine.mid_frame() {
+                if let Some(movie) = &replay
+                    && !movie.play(frame, &mut machine) && frame == movie.len()
+                {
+                    osd.message("Replay finished");
+                }
+                if let Some(movie) = &mut recording {
+                    movie.record(&machine);
+                }
+                frame += 1;
+                rewind.push(&machine);
             }
-            quirks.memory_increment_by_x(cpu, x)?;
-            quirks.memory_leave_i_unchanged(cpu, x)?;
-        },
-        0x75 => {
-            for i in 0..=x {
-                cpu.flag[i] = cpu.v[i];
+            match machine.run_frame() {
+                Err(chip8::EmuError::Exit()) => {
+                    if let Some(capture) = capture.take() {
+                        capture.finish()?;
+                    }
+                    save_movie(recording.as_ref(), args.record.as_deref())?;
+                    return Err(chip8::EmuError::Exit().into());
+                },
+                Err(chip8::EmuError::Breakpoint(_)) => {
+                    if let Some(debugger) = &mut debugger {
+                        debugger.pause(&machine);
+                    }
+                    break;
+                },
+                Err(e) => osd.error(e),
+                _ => (),
             }
-        },
-        0x85 => {
-            for i in 0..=x {
-                cpu.v[i] = cpu.flag[i];
+            audio.update(&machine);
+            renderer.frame(&machine.cpu);
+            if let Some(recording) = &mut capture
+                && let Err(e) = recording.frame(&machine)
+            {
+                osd.error(e);
+                capture = None;
             }
         }
-        _ => return Err(EmuError::Invalid(cpu.opcode)),
-    }
-    Ok(())
-}
-
-fn draw_sprite(cpu: &mut Cpu, quirks: &Quirks, x: u16, y: u16) {
-    let cols = if cpu.hires { 128 } else { 64 };
-    let rows = if cpu.hires { 64 } else { 32 };
-    for row in 0..(cpu.opcode & 0x000F) {
-        for col in 0..8 {
-            if quirks.wrap || ((y + row < rows) && (x + col < cols)) {
-                let sprite_pixel = cpu.memory[(cpu.i + row) as usize] & (0x80 >> col);
-                let screen_pixel = &mut cpu.display_buffer[(((y + row) * cols) + x + col) as usize];
-                if sprite_pixel != 0 {
-                    if *screen_pixel {
-                        cpu.v[0xF] = 1;
-                    }
-                    *screen_pixel ^= true;
-                }
+        if let Some((frame_rate, ips, refresh_rate)) = fps.update(frames, machine.instructions() - instructions) {
+            let speed = frame_rate / f64::from(TIMER_RATE) * 100.0;
+            let report = format!("{frame_rate:.1} fps ({speed:.0}%), {ips:.0} ips, {refresh_rate:.1} hz");
+            renderer.set_title(&format!("CHIP-8 Emulator - {report}"))?;
+            if args.fps {
+                println!("{report}");
             }
+            osd.set_stats(frame_rate, ips);
         }
-    }
-}
-
-fn draw_super_sprite(cpu: &mut Cpu, quirks: &Quirks, x: u16, y: u16) {
-    let cols = if cpu.hires { 128 } else { 64 };
-    let rows = if cpu.hires { 64 } else { 32 };
-    for row in 0..16 {
-        let i = usize::from(cpu.i + row * 2);
-        let addr = u16::from_be_bytes([cpu.memory[i], cpu.memory[i + 1]]);
-        for col in 0..16 {
-            if quirks.wrap || ((y + row < rows) && (x + col < cols)) {
-                let sprite_pixel = addr & (0x8000 >> col);
-                let screen_pixel = &mut cpu.display_buffer[(((y + row) * cols) + x + col) as usize];
-                if sprite_pixel != 0 {
-                    if *screen_pixel {
-                        cpu.v[0xF] = 1;
-                    }
-                    *screen_pixel ^= true;
-                }
-            }
+        osd.set_status(if rewinding {
+            Some("Rewinding".to_owned())
+        } else if paused || debugger.as_ref().is_some_and(|debugger| debugger.paused) {
+            Some("Paused".to_owned())
+        } else if slow_motion {
+            Some(format!("Slow motion 1/{}", args.slow_motion.max(1)))
+        } else if fast_forward {
+            Some(format!("Fast forward x{}", args.fast_forward))
+        } else {
+            None
+        });
+        if machine.take_dirty() | renderer.animating() | osd.take_dirty() {
+            renderer.draw(&machine.cpu, &osd)?;
         }
+        pacer.wait();
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,141 @@
+//! The on-screen display: speed statistics, the emulation state and transient
+//! messages, drawn by `Renderer` on top of the game without touching the
+//! display buffer.
+use chip8::FONT;
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+/// How long a message stays on screen.
+const MESSAGE_TIME: Duration = Duration::from_secs(3);
+
+pub struct Osd {
+    visible: bool,
+    /// The frame and instruction rates, updated about once a second
+    stats: String,
+    /// Paused, fast-forwarding and the like, if the emulation isn't running normally
+    status: Option<String>,
+    message: Option<(String, Instant)>,
+    /// Whether the text changed since the last draw
+    dirty: bool,
+}
+
+impl Osd {
+    pub fn new(visible: bool) -> Osd {
+        Osd { visible, stats: String::new(), status: None, message: None, dirty: true }
+    }
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.dirty = true;
+    }
+    pub fn set_stats(&mut self, frame_rate: f64, ips: f64) {
+        self.stats = format!("{frame_rate:.1} FPS {ips:.0} IPS");
+        self.dirty |= self.visible;
+    }
+    pub fn set_status(&mut self, status: Option<String>) {
+        if self.status != status {
+            self.status = status;
+            self.dirty |= self.visible;
+        }
+    }
+    /// Shows `text` for a few seconds, also printing it to stdout.
+    pub fn message(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        println!("{text}");
+        self.message = Some((text, Instant::now()));
+        self.dirty |= self.visible;
+    }
+    /// Shows an error like `message`, printing it to stderr instead.
+    pub fn error(&mut self, error: impl Display) {
+        let text = format!("Error: {error}");
+        eprintln!("{text}");
+        self.message = Some((text, Instant::now()));
+        self.dirty |= self.visible;
+    }
+    /// Whether the OSD has to be redrawn, expiring old messages first.
+    pub fn take_dirty(&mut self) -> bool {
+        if self.message.as_ref().is_some_and(|(_, shown)| shown.elapsed() >= MESSAGE_TIME) {
+            self.message = None;
+            self.dirty |= self.visible;
+        }
+        std::mem::take(&mut self.dirty)
+    }
+    /// The lines to show at the top left and the message for the bottom left.
+    pub fn text(&self) -> (Vec<&str>, Option<&str>) {
+        if !self.visible {
+            return (Vec::new(), None);
+        }
+        let top = [Some(self.stats.as_str()), self.status.as_deref()].into_iter().flatten().filter(|line| !line.is_empty());
+        (top.collect(), self.message.as_ref().map(|(text, _)| text.as_str()))
+    }
+}
+
+/// The 4x5 glyph of `c`, one row per byte in the high nibble like `FONT`, whose
+/// digits and letters A to F it reuses. Letters are shown in upper case and
+/// characters outside printable ASCII as `?`.
+pub fn glyph(c: char) -> [u8; 5] {
+    let c = c.to_ascii_uppercase();
+    let font = |index: usize| {
+        let mut glyph = [0; 5];
+        glyph.copy_from_slice(&FONT[index * 5..index * 5 + 5]);
+        glyph
+    };
+    match c {
+        '0'..='9' => font(c as usize - '0' as usize),
+        'A'..='F' => font(c as usize - 'A' as usize + 10),
+        'G' => [0xF0, 0x80, 0xB0, 0x90, 0xF0],
+        'H' => [0x90, 0x90, 0xF0, 0x90, 0x90],
+        'I' => [0xE0, 0x40, 0x40, 0x40, 0xE0],
+        'J' => [0x10, 0x10, 0x10, 0x90, 0xF0],
+        'K' => [0x90, 0xA0, 0xC0, 0xA0, 0x90],
+        'L' => [0x80, 0x80, 0x80, 0x80, 0xF0],
+        'M' => [0x90, 0xF0, 0xF0, 0x90, 0x90],
+        'N' => [0x90, 0xD0, 0xB0, 0x90, 0x90],
+        'O' => [0x60, 0x90, 0x90, 0x90, 0x60],
+        'P' => [0xF0, 0x90, 0xF0, 0x80, 0x80],
+        'Q' => [0x60, 0x90, 0x90, 0xB0, 0x70],
+        'R' => [0xE0, 0x90, 0xE0, 0xA0, 0x90],
+        'S' => [0x70, 0x80, 0x60, 0x10, 0xE0],
+        'T' => [0xE0, 0x40, 0x40, 0x40, 0x40],
+        'U' => [0x90, 0x90, 0x90, 0x90, 0xF0],
+        'V' => [0x90, 0x90, 0x90, 0x60, 0x60],
+        'W' => [0x90, 0x90, 0xF0, 0xF0, 0x90],
+        'X' => [0x90, 0x90, 0x60, 0x90, 0x90],
+        'Y' => [0xA0, 0xA0, 0x40, 0x40, 0x40],
+        'Z' => [0xF0, 0x10, 0x60, 0x80, 0xF0],
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00],
+        '!' => [0x40, 0x40, 0x40, 0x00, 0x40],
+        '"' => [0xA0, 0xA0, 0x00, 0x00, 0x00],
+        '#' => [0xA0, 0xF0, 0xA0, 0xF0, 0xA0],
+        '$' => [0x70, 0xA0, 0x60, 0x50, 0xE0],
+        '%' => [0x90, 0x10, 0x60, 0x80, 0x90],
+        '&' => [0x40, 0xA0, 0x40, 0xA0, 0x50],
+        '\'' => [0x40, 0x40, 0x00, 0x00, 0x00],
+        '(' => [0x20, 0x40, 0x40, 0x40, 0x20],
+        ')' => [0x40, 0x20, 0x20, 0x20, 0x40],
+        '*' => [0x00, 0xA0, 0x40, 0xA0, 0x00],
+        '+' => [0x00, 0x40, 0xE0, 0x40, 0x00],
+        ',' => [0x00, 0x00, 0x00, 0x40, 0x80],
+        '-' => [0x00, 0x00, 0xF0, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x40],
+        '/' => [0x10, 0x20, 0x20, 0x40, 0x80],
+        ':' => [0x00, 0x40, 0x00, 0x40, 0x00],
+        ';' => [0x00, 0x40, 0x00, 0x40, 0x80],
+        '<' => [0x20, 0x40, 0x80, 0x40, 0x20],
+        '=' => [0x00, 0xF0, 0x00, 0xF0, 0x00],
+        '>' => [0x80, 0x40, 0x20, 0x40, 0x80],
+        '@' => [0x60, 0x90, 0xB0, 0x80, 0x70],
+        '[' => [0x60, 0x40, 0x40, 0x40, 0x60],
+        '\\' => [0x80, 0x40, 0x40, 0x20, 0x10],
+        ']' => [0x60, 0x20, 0x20, 0x20, 0x60],
+        '^' => [0x40, 0xA0, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0xF0],
+        '`' => [0x80, 0x40, 0x00, 0x00, 0x00],
+        '{' => [0x60, 0x40, 0x80, 0x40, 0x60],
+        '|' => [0x40, 0x40, 0x40, 0x40, 0x40],
+        '}' => [0xC0, 0x40, 0x20, 0x40, 0xC0],
+        '~' => [0x00, 0x50, 0xA0, 0x00, 0x00],
+        _ => [0xE0, 0x10, 0x60, 0x00, 0x40],
+    }
+}
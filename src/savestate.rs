@@ -0,0 +1,32 @@
+use crate::EmuError;
+use chip8::Machine;
+use std::{fs, path::PathBuf};
+
+const SLOTS: u8 = 10;
+
+/// Save state slots stored next to the ROM as `<rom>.st0` to `<rom>.st9`.
+pub struct SaveSlots {
+    rom: PathBuf,
+    pub slot: u8,
+}
+
+impl SaveSlots {
+    pub fn new(rom: impl Into<PathBuf>) -> SaveSlots {
+        SaveSlots { rom: rom.into(), slot: 0 }
+    }
+    fn path(&self) -> PathBuf {
+        self.rom.with_extension(format!("st{}", self.slot))
+    }
+    /// Selects the next slot, or the previous one if `back` is set, wrapping around.
+    pub fn select(&mut self, back: bool) {
+        self.slot = if back { (self.slot + SLOTS - 1) % SLOTS } else { (self.slot + 1) % SLOTS };
+    }
+    pub fn save(&self, machine: &Machine) -> Result<(), EmuError> {
+        fs::write(self.path(), machine.save_state())?;
+        Ok(())
+    }
+    pub fn load(&self, machine: &mut Machine) -> Result<(), EmuError> {
+        machine.load_state(&fs::read(self.path())?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+//! Built-in colour themes and palette files.
+//!
+//! A palette file lists up to four hex colours, one per line, for pixels lit on
+//! no plane, plane 1, plane 2 and both planes. Blank lines and lines starting
+//! with `;` are ignored.
+use crate::{draw, EmuError};
+use sdl2::pixels::Color;
+use std::{fmt, fs, path::Path, str::FromStr};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Theme {
+    pub name: &'static str,
+    pub palette: [Color; 4],
+}
+
+pub const THEMES: [Theme; 6] = [
+    Theme { name: "classic", palette: draw::PALETTE },
+    Theme {
+        name: "octo",
+        palette: [Color::RGB(0x99, 0x66, 0x00), Color::RGB(0xFF, 0xCC, 0x00), Color::RGB(0xFF, 0x66, 0x00), Color::RGB(0x66, 0x22, 0x00)],
+    },
+    Theme {
+        name: "lcd",
+        palette: [Color::RGB(0x9B, 0xBC, 0x0F), Color::RGB(0x0F, 0x38, 0x0F), Color::RGB(0x30, 0x62, 0x30), Color::RGB(0x8B, 0xAC, 0x0F)],
+    },
+    Theme {
+        name: "amber",
+        palette: [Color::RGB(0x1A, 0x10, 0x00), Color::RGB(0xFF, 0xB0, 0x00), Color::RGB(0xB3, 0x6B, 0x00), Color::RGB(0xFF, 0xD8, 0x80)],
+    },
+    Theme {
+        name: "high-contrast",
+        palette: [Color::BLACK, Color::WHITE, Color::YELLOW, Color::CYAN],
+    },
+    // The Okabe-Ito palette, distinguishable with all common kinds of colour blindness
+    Theme {
+        name: "colorblind",
+        palette: [Color::BLACK, Color::RGB(0xE6, 0x9F, 0x00), Color::RGB(0x56, 0xB4, 0xE9), Color::RGB(0xF0, 0xE4, 0x42)],
+    },
+];
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        THEMES.iter().find(|theme| theme.name == s).copied().ok_or_else(|| {
+            let names: Vec<_> = THEMES.iter().map(|theme| theme.name).collect();
+            format!("unknown theme '{s}', expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+/// Reads the colours of a palette file.
+pub fn load(path: &Path) -> Result<Vec<Color>, EmuError> {
+    let colors = fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .map(draw::parse_color)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| EmuError::Palette(format!("{}: {e}", path.display())))?;
+    if colors.len() > 4 {
+        return Err(EmuError::Palette(format!("{}: expected at most 4 colours, found {}", path.display(), colors.len())));
+    }
+    Ok(colors)
+}
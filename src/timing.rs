@@ -0,0 +1,116 @@
+//! Pacing against a monotonic clock, so emulation runs at 60 Hz whatever the
+//! refresh rate and sleeping late on one iteration is made up on the next.
+use std::time::{Duration, Instant};
+
+/// The rate of the CHIP-8 timers, and so of emulated frames.
+pub const TIMER_RATE: u32 = 60;
+/// How long before a deadline to stop sleeping and spin instead, as sleeps
+/// commonly overshoot by around a millisecond.
+const SPIN: Duration = Duration::from_millis(1);
+/// The most time made up at once after a stall, so a long hiccup skips time
+/// instead of fast-forwarding through it. Raised to two refresh periods at slow
+/// refresh rates so that they still run every tick.
+const MAX_LAG: Duration = Duration::from_millis(250);
+
+/// Counts the ticks of a fixed rate clock that came due since the last check.
+pub struct Ticker {
+    period: Duration,
+    max_lag: Duration,
+    next: Instant,
+}
+
+impl Ticker {
+    /// Ticks at `rate` per second, checked `refresh_rate` times a second or as
+    /// often as possible if it is 0.
+    pub fn new(rate: u32, refresh_rate: u32) -> Ticker {
+        let period = Duration::from_secs(1) / rate.max(1);
+        let max_lag = MAX_LAG.max(Duration::from_secs(2).checked_div(refresh_rate).unwrap_or_default());
+        Ticker { period, max_lag, next: Instant::now() + period }
+    }
+    /// Returns the number of ticks due since the last call.
+    pub fn due(&mut self) -> u32 {
+        let now = Instant::now();
+        if now.saturating_duration_since(self.next) > self.max_lag {
+            self.next = now.checked_sub(self.max_lag).unwrap_or(now);
+        }
+        let mut ticks = 0;
+        while self.next <= now {
+            ticks += 1;
+            self.next += self.period;
+        }
+        ticks
+    }
+    /// Starts counting afresh from now, dropping any ticks missed while paused.
+    pub fn resync(&mut self) {
+        self.next = Instant::now() + self.period;
+    }
+}
+
+/// Sleeps until fixed deadlines, each a period after the last rather than after
+/// the end of the sleep, so time spent working doesn't accumulate as drift.
+pub struct Pacer {
+    period: Option<Duration>,
+    next: Instant,
+}
+
+impl Pacer {
+    /// Paces at `rate` per second, or not at all if it is 0.
+    pub fn new(rate: u32) -> Pacer {
+        let period = Duration::from_secs(1).checked_div(rate);
+        Pacer { period, next: Instant::now() }
+    }
+    pub fn wait(&mut self) {
+        let Some(period) = self.period else {
+            return;
+        };
+        self.next += period;
+        let now = Instant::now();
+        let Some(remaining) = self.next.checked_duration_since(now) else {
+            // More than a period behind, so start over rather than rushing to catch up
+            self.next = now;
+            return;
+        };
+        if let Some(sleep) = remaining.checked_sub(SPIN) {
+            std::thread::sleep(sleep);
+        }
+        while Instant::now() < self.next {
+            std::thread::yield_now();
+        }
+    }
+}
+
+/// Measures the achieved rates of emulated frames and refreshes.
+pub struct FpsCounter {
+    since: Instant,
+    frames: u32,
+    instructions: u64,
+    refreshes: u32,
+}
+
+impl Default for FpsCounter {
+    fn default() -> Self {
+        FpsCounter { since: Instant::now(), frames: 0, instructions: 0, refreshes: 0 }
+    }
+}
+
+impl FpsCounter {
+    /// Counts a refresh that emulated `frames` frames and `instructions`
+    /// instructions, returning the frames, instructions and refreshes per second
+    /// about once a second.
+    pub fn update(&mut self, frames: u32, instructions: u64) -> Option<(f64, f64, f64)> {
+        self.frames += frames;
+        self.instructions += instructions;
+        self.refreshes += 1;
+        let elapsed = self.since.elapsed().as_secs_f64();
+        if elapsed < 1.0 {
+            return None;
+        }
+        let rates = (
+            f64::from(self.frames) / elapsed,
+            self.instructions as f64 / elapsed,
+            f64::from(self.refreshes) / elapsed,
+        );
+        *self = FpsCounter::default();
+        Some(rates)
+    }
+}